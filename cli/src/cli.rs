@@ -1,5 +1,16 @@
+use anyhow::{bail, Result};
 use clap::Parser;
+use futures_util::{Stream, StreamExt};
+use rig_core::auth::configured_store;
+use rig_core::config::{CacheConfig, Profiles};
+use rig_core::http::{HttpCache, ListOptions};
+use rig_core::{AuthClient, Config, HttpClient};
+use std::io::{self, Read};
 use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::info;
 
 use crate::commands::Commands;
 
@@ -24,7 +35,8 @@ pub struct GlobalOpts {
     #[arg(long, short = 'o', value_enum, default_value = "table")]
     pub output: OutputFormat,
 
-    /// Enable verbose logging
+    /// Enable verbose logging, tracing HTTP requests and socket frames with
+    /// credentials redacted
     #[arg(long, short)]
     pub verbose: bool,
 
@@ -35,6 +47,21 @@ pub struct GlobalOpts {
     /// Configuration file path
     #[arg(long, short)]
     pub config: Option<PathBuf>,
+
+    /// Auth profile to use; defaults to the active profile
+    #[arg(long, global = true, env = "RIG_PROFILE")]
+    pub profile: Option<String>,
+
+    /// API token to use instead of the stored login; never persisted
+    #[arg(long, global = true, env = "RIG_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Read the API token from stdin; takes precedence over --token
+    #[arg(long, global = true)]
+    pub token_stdin: bool,
+
+    #[arg(skip)]
+    stdin_token: OnceLock<String>,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -44,8 +71,164 @@ pub enum OutputFormat {
     Yaml,
 }
 
+/// Items list commands show unless told otherwise
+const DEFAULT_LIMIT: usize = 50;
+
+/// The `--limit`/`--all` options every list command shares
+#[derive(clap::Args, Debug, Clone)]
+pub struct ListArgs {
+    /// Show at most this many items
+    #[arg(long, default_value_t = DEFAULT_LIMIT, conflicts_with = "all")]
+    pub limit: usize,
+
+    /// Show every item, however many pages that takes
+    #[arg(long)]
+    pub all: bool,
+
+    /// Items to fetch per request; the server default applies when omitted
+    #[arg(long)]
+    pub page_size: Option<u32>,
+}
+
+impl ListArgs {
+    /// Asks for one item past the limit, to tell whether any were left out
+    pub fn options(&self) -> ListOptions {
+        ListOptions {
+            page_size: self.page_size,
+            limit: (!self.all).then_some(self.limit + 1),
+        }
+    }
+
+    /// Collects the items to show, noting when the limit left some out
+    pub async fn collect<T>(
+        &self,
+        items: impl Stream<Item = rig_core::Result<T>>,
+    ) -> Result<Vec<T>> {
+        let mut items = pin!(items);
+        let mut collected = Vec::new();
+        while let Some(item) = items.next().await {
+            if !self.all && collected.len() == self.limit {
+                info!(
+                    "Showing the first {}; pass --all to list everything",
+                    self.limit
+                );
+                break;
+            }
+            collected.push(item?);
+        }
+        Ok(collected)
+    }
+}
+
+/// The profile and endpoint a command runs against
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub config: Config,
+    pub profile: String,
+    pub endpoint: String,
+}
+
+impl Session {
+    pub fn http_client(&self) -> Result<HttpClient> {
+        let client = HttpClient::from_config(&self.endpoint, &self.config.connection)?;
+        if !self.config.cache.enabled {
+            return Ok(client);
+        }
+        Ok(client.with_cache(http_cache(&self.config.cache)?))
+    }
+
+    /// An `AuthClient` for the profile, using the configured credential store
+    pub fn auth_client(&self) -> Result<AuthClient> {
+        let store = configured_store(&self.config.auth)?;
+        Ok(AuthClient::with_store(
+            self.http_client()?,
+            &self.profile,
+            store,
+        ))
+    }
+}
+
+/// The response cache under the config directory, sized by `config`
+pub fn http_cache(config: &CacheConfig) -> Result<HttpCache> {
+    Ok(HttpCache::new(Config::cache_dir()?)
+        .ttl(Duration::from_secs(config.ttl))
+        .max_size(config.max_size_mb.saturating_mul(1024 * 1024)))
+}
+
 impl GlobalOpts {
     pub fn is_json_output(&self) -> bool {
         self.json || matches!(self.output, OutputFormat::Json)
     }
+
+    pub fn is_yaml_output(&self) -> bool {
+        !self.json && matches!(self.output, OutputFormat::Yaml)
+    }
+
+    /// Loads `--config`, falling back to the default config file if it exists
+    pub fn load_config(&self) -> Result<Config> {
+        let path = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => Config::default_config_path()
+                .ok()
+                .filter(|path| path.exists()),
+        };
+        Config::load(path)
+    }
+
+    /// The token given by `--token-stdin`, `--token` or `RIG_TOKEN`, if any
+    pub fn token(&self) -> Result<Option<String>> {
+        let token = if self.token_stdin {
+            if self.stdin_token.get().is_none() {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input)?;
+                let _ = self.stdin_token.set(input.trim().to_string());
+            }
+            self.stdin_token.get().cloned()
+        } else {
+            self.token.as_ref().map(|token| token.trim().to_string())
+        };
+
+        match token {
+            Some(token) if token.is_empty() => bail!("The provided token is empty"),
+            token => Ok(token),
+        }
+    }
+
+    pub fn load_profiles(&self) -> Result<Profiles> {
+        Profiles::load(&Config::profiles_path()?)
+    }
+
+    /// Resolves the profile from `--profile` or the active profile, and its
+    /// endpoint from `endpoint`, the profile, or the config, in that order
+    pub fn session(&self, endpoint: Option<String>) -> Result<Session> {
+        let config = self.load_config()?;
+        let profiles = self.load_profiles()?;
+        let profile = profiles.resolve(self.profile.as_deref()).to_string();
+        let endpoint = endpoint
+            .or_else(|| profiles.get(&profile).map(|p| p.endpoint.clone()))
+            .unwrap_or_else(|| config.connection.base_url.clone());
+
+        Ok(Session {
+            config,
+            profile,
+            endpoint,
+        })
+    }
+
+    /// Builds an `AuthClient` for the session's profile that honors the
+    /// provided token ahead of the keyring
+    pub fn auth_client(&self, session: &Session) -> Result<AuthClient> {
+        let auth_client = session.auth_client()?;
+        Ok(match self.token()? {
+            Some(token) => auth_client.with_token(token),
+            None => auth_client,
+        })
+    }
+
+    /// An `HttpClient` for the session that authenticates every request
+    /// with the profile's token, refreshing it when the server rejects it
+    pub fn api_client(&self, session: &Session) -> Result<HttpClient> {
+        let auth_client = Arc::new(self.auth_client(session)?);
+        Ok(session.http_client()?.with_token_provider(auth_client))
+    }
 }
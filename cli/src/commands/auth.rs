@@ -1,22 +1,220 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use rig_core::Config;
+use rig_utils::format_duration;
+use serde_json::json;
+use std::io::{self, BufRead, IsTerminal, Write};
 use tracing::{info, warn};
 
-use crate::cli::GlobalOpts;
+use crate::cli::{GlobalOpts, Session};
 
-pub async fn login_command(endpoint: Option<String>, global_opts: &GlobalOpts) -> Result<()> {
+pub async fn login_command(
+    endpoint: Option<String>,
+    username: Option<String>,
+    global_opts: &GlobalOpts,
+) -> Result<()> {
     info!("Starting login process");
 
-    let endpoint = endpoint.unwrap_or_else(|| "https://api.max.dev".to_string());
+    let session = global_opts.session(endpoint)?;
+    let endpoint = session.endpoint.clone();
+
+    if use_provided_token(&endpoint, global_opts)? {
+        return Ok(());
+    }
+
+    if !io::stdin().is_terminal() {
+        bail!("Interactive login requires a terminal; use --token, --token-stdin or RIG_TOKEN");
+    }
+
+    let username = match username {
+        Some(username) => username,
+        None => prompt("Username: ")?,
+    };
+    if username.is_empty() {
+        bail!("Username cannot be empty");
+    }
+    let password = rpassword::prompt_password("Password: ")?;
+
+    let auth_client = global_opts.auth_client(&session)?;
+    auth_client.login(&username, &password).await?;
+
+    save_profile(&session, global_opts)?;
+
+    info!(
+        "Logged in to {} as {} (profile '{}')",
+        endpoint, username, session.profile
+    );
+
+    if global_opts.is_json_output() {
+        println!(
+            "{}",
+            json!({
+                "status": "logged_in",
+                "profile": session.profile,
+                "endpoint": endpoint,
+                "username": username,
+            })
+        );
+    } else {
+        println!(
+            "✅ Logged in to {} as {} (profile '{}')",
+            endpoint, username, session.profile
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn device_login_command(
+    endpoint: Option<String>,
+    global_opts: &GlobalOpts,
+) -> Result<()> {
+    info!("Starting device login");
+
+    let session = global_opts.session(endpoint)?;
+    let endpoint = session.endpoint.clone();
+
+    if use_provided_token(&endpoint, global_opts)? {
+        return Ok(());
+    }
+
+    let auth_client = global_opts.auth_client(&session)?;
+    auth_client
+        .device_login(|code| {
+            // Instructions go to stderr so `--json` output stays parseable
+            let url = code
+                .verification_uri_complete
+                .as_deref()
+                .unwrap_or(&code.verification_uri);
+            eprintln!("🔑 Open {} and enter the code: {}", url, code.user_code);
+            eprintln!("Waiting for approval...");
+        })
+        .await?;
+
+    save_profile(&session, global_opts)?;
+
+    info!("Logged in to {} (profile '{}')", endpoint, session.profile);
 
     if global_opts.is_json_output() {
         println!(
-            r#"{{"status": "not_implemented", "message": "Login functionality coming soon"}}"#
+            "{}",
+            json!({"status": "logged_in", "profile": session.profile, "endpoint": endpoint})
         );
     } else {
-        println!("🔐 Login functionality coming soon...");
-        println!("Will authenticate with endpoint: {}", endpoint);
+        println!(
+            "✅ Logged in to {} (profile '{}')",
+            endpoint, session.profile
+        );
     }
 
-    warn!("Login not yet implemented - placeholder only");
     Ok(())
 }
+
+pub async fn logout_command(global_opts: &GlobalOpts) -> Result<()> {
+    let session = global_opts.session(None)?;
+    session.auth_client()?.logout()?;
+
+    info!(
+        "Logged out of {} (profile '{}')",
+        session.endpoint, session.profile
+    );
+
+    if global_opts.is_json_output() {
+        println!(
+            "{}",
+            json!({
+                "status": "logged_out",
+                "profile": session.profile,
+                "endpoint": session.endpoint,
+            })
+        );
+    } else {
+        println!(
+            "👋 Logged out of {} (profile '{}')",
+            session.endpoint, session.profile
+        );
+    }
+
+    if global_opts.token()?.is_some() {
+        warn!("A token from --token or RIG_TOKEN is still in use for this command");
+    }
+    Ok(())
+}
+
+pub async fn whoami_command(global_opts: &GlobalOpts) -> Result<()> {
+    let session = global_opts.session(None)?;
+    let auth_client = global_opts.auth_client(&session)?;
+
+    let identity = auth_client.whoami().await?;
+    let token = auth_client.valid_token().await?;
+    let expires_in = token.as_ref().and_then(|token| token.expires_in());
+    let scopes = token
+        .and_then(|token| token.claims())
+        .map(|claims| claims.scopes)
+        .unwrap_or_default();
+
+    let output = json!({
+        "username": identity.username,
+        "email": identity.email,
+        "org": identity.org,
+        "profile": session.profile,
+        "endpoint": session.endpoint,
+        "expires_in": expires_in.map(|d| d.as_secs()),
+        "scopes": scopes,
+    });
+
+    if global_opts.is_json_output() {
+        println!("{}", output);
+    } else if global_opts.is_yaml_output() {
+        print!("{}", serde_yaml::to_string(&output)?);
+    } else {
+        let expiry = expires_in.map_or("never".to_string(), |d| format_duration(d.as_secs()));
+        println!("👤 User:     {}", identity.username);
+        if let Some(email) = &identity.email {
+            println!("   Email:    {}", email);
+        }
+        println!("   Org:      {}", identity.org.as_deref().unwrap_or("-"));
+        println!("   Profile:  {}", session.profile);
+        println!("   Endpoint: {}", session.endpoint);
+        println!("   Expires:  {}", expiry);
+        if !scopes.is_empty() {
+            println!("   Scopes:   {}", scopes.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Reports a `--token`/`RIG_TOKEN` token instead of logging in; returns
+/// whether one was provided
+fn use_provided_token(endpoint: &str, global_opts: &GlobalOpts) -> Result<bool> {
+    if global_opts.token()?.is_none() {
+        return Ok(false);
+    }
+
+    info!("Using the provided token for {}", endpoint);
+
+    if global_opts.is_json_output() {
+        println!(
+            "{}",
+            json!({"status": "token_provided", "endpoint": endpoint, "persisted": false})
+        );
+    } else {
+        println!("✅ Using the provided token for {} (not stored)", endpoint);
+    }
+    Ok(true)
+}
+
+fn save_profile(session: &Session, global_opts: &GlobalOpts) -> Result<()> {
+    let mut profiles = global_opts.load_profiles()?;
+    profiles.upsert(&session.profile, &session.endpoint);
+    profiles.save(&Config::profiles_path()?)
+}
+
+fn prompt(label: &str) -> Result<String> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{label}")?;
+    stderr.flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
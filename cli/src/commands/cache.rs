@@ -0,0 +1,29 @@
+use anyhow::Result;
+use serde_json::json;
+
+use crate::cli::{http_cache, GlobalOpts};
+use crate::commands::CacheAction;
+
+pub fn cache_command(action: &CacheAction, global_opts: &GlobalOpts) -> Result<()> {
+    match action {
+        CacheAction::Clear => clear(global_opts),
+    }
+}
+
+fn clear(global_opts: &GlobalOpts) -> Result<()> {
+    let config = global_opts.load_config()?;
+    let cache = http_cache(&config.cache)?;
+    let removed = cache.clear()?;
+
+    if global_opts.is_json_output() {
+        println!(
+            "{}",
+            json!({"removed": removed, "dir": cache.dir().display().to_string()})
+        );
+    } else if removed == 0 {
+        println!("The cache is already empty");
+    } else {
+        println!("✅ Removed {} cached response(s)", removed);
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::{bail, Result};
+use rig_core::auth::{credential_key, migrate_credentials, open_store};
+use rig_core::config::{CredentialStoreKind, DEFAULT_PROFILE};
+use serde_json::json;
+use tracing::info;
+use url::Url;
+
+use crate::cli::GlobalOpts;
+use crate::commands::CredentialsAction;
+
+pub async fn credentials_command(
+    action: &CredentialsAction,
+    global_opts: &GlobalOpts,
+) -> Result<()> {
+    match action {
+        CredentialsAction::Migrate { from, to } => migrate(*from, *to, global_opts),
+    }
+}
+
+fn migrate(
+    from: CredentialStoreKind,
+    to: CredentialStoreKind,
+    global_opts: &GlobalOpts,
+) -> Result<()> {
+    if from == CredentialStoreKind::Memory || to == CredentialStoreKind::Memory {
+        bail!("The memory store does not outlive the command and cannot be migrated");
+    }
+
+    let from = open_store(from)?;
+    let to = open_store(to)?;
+    if from.name() == to.name() {
+        bail!("Both stores resolve to the {} store", from.name());
+    }
+
+    let config = global_opts.load_config()?;
+    let profiles = global_opts.load_profiles()?;
+    let mut keys = vec![credential_key(
+        DEFAULT_PROFILE,
+        &Url::parse(&config.connection.base_url)?,
+    )];
+    for (name, profile) in &profiles.profiles {
+        keys.push(credential_key(name, &Url::parse(&profile.endpoint)?));
+    }
+    keys.sort();
+    keys.dedup();
+
+    let moved = migrate_credentials(from.as_ref(), to.as_ref(), &keys)?;
+
+    info!(
+        "Moved {} credentials from {} to {}",
+        moved.len(),
+        from.name(),
+        to.name()
+    );
+
+    if global_opts.is_json_output() {
+        println!(
+            "{}",
+            json!({"from": from.name(), "to": to.name(), "migrated": moved})
+        );
+    } else if moved.is_empty() {
+        println!("No credentials found in the {} store", from.name());
+    } else {
+        println!(
+            "✅ Moved {} credential(s) from the {} store to the {} store",
+            moved.len(),
+            from.name(),
+            to.name()
+        );
+    }
+    Ok(())
+}
@@ -1,10 +1,15 @@
 use anyhow::Result;
 use clap::Subcommand;
+use rig_core::config::CredentialStoreKind;
 
-use crate::cli::GlobalOpts;
+use crate::cli::{GlobalOpts, ListArgs};
 
 pub mod auth;
+pub mod cache;
+pub mod credentials;
+pub mod profile;
 pub mod status;
+pub mod tokens;
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -13,6 +18,32 @@ pub enum Commands {
         /// API endpoint URL
         #[arg(long)]
         endpoint: Option<String>,
+
+        /// Username to log in as; prompted for when omitted
+        #[arg(long, short)]
+        username: Option<String>,
+
+        /// Sign in through the browser with a device code instead of a password
+        #[arg(long, conflicts_with = "username")]
+        device: bool,
+    },
+
+    /// Remove the stored credentials for the current profile
+    Logout,
+
+    /// Show who you are logged in as
+    Whoami,
+
+    /// Manage stored credentials
+    Credentials {
+        #[command(subcommand)]
+        action: CredentialsAction,
+    },
+
+    /// Manage auth profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
     },
 
     /// Show status information
@@ -21,10 +52,88 @@ pub enum Commands {
         target: Option<StatusTarget>,
     },
 
+    /// Manage API tokens for automation
+    Tokens {
+        #[command(subcommand)]
+        action: TokensAction,
+    },
+
+    /// Manage the on-disk response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
     /// Show version information
     Version,
 }
 
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Remove every cached response
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum CredentialsAction {
+    /// Move every profile's credentials from one store to another
+    Migrate {
+        /// Store to move from: auto, keyring, file
+        #[arg(long)]
+        from: CredentialStoreKind,
+
+        /// Store to move to: auto, keyring, file
+        #[arg(long)]
+        to: CredentialStoreKind,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    /// List profiles and their endpoints
+    List,
+    /// Make a profile the default for later commands
+    Use {
+        /// Profile name
+        name: String,
+    },
+    /// Remove a profile and its stored credentials
+    Remove {
+        /// Profile name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TokensAction {
+    /// Create a token; its secret is shown only once
+    Create {
+        /// A name to recognise the token by
+        #[arg(long)]
+        name: String,
+
+        /// Scopes to grant, comma separated
+        #[arg(long, value_delimiter = ',', required = true)]
+        scopes: Vec<String>,
+
+        /// Lifetime, e.g. 12h or 30d; the server default applies when omitted
+        #[arg(long)]
+        ttl: Option<String>,
+
+        /// Key for the create request, so re-running it, e.g. in CI, returns
+        /// the first token instead of creating another; random when omitted
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
+    /// List tokens; secrets are never shown
+    List(ListArgs),
+    /// Revoke a token
+    Revoke {
+        /// Token id
+        id: String,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum StatusTarget {
     /// Show dashboard overview
@@ -44,12 +153,28 @@ pub enum StatusTarget {
 impl Commands {
     pub async fn execute(&self, global_opts: &GlobalOpts) -> Result<()> {
         match self {
-            Commands::Login { endpoint } => {
-                auth::login_command(endpoint.clone(), global_opts).await
+            Commands::Login {
+                endpoint,
+                username,
+                device,
+            } => {
+                if *device {
+                    auth::device_login_command(endpoint.clone(), global_opts).await
+                } else {
+                    auth::login_command(endpoint.clone(), username.clone(), global_opts).await
+                }
+            }
+            Commands::Logout => auth::logout_command(global_opts).await,
+            Commands::Whoami => auth::whoami_command(global_opts).await,
+            Commands::Credentials { action } => {
+                credentials::credentials_command(action, global_opts).await
             }
+            Commands::Profile { action } => profile::profile_command(action, global_opts).await,
             Commands::Status { target } => {
                 status::status_command(target.as_ref(), global_opts).await
             }
+            Commands::Tokens { action } => tokens::tokens_command(action, global_opts).await,
+            Commands::Cache { action } => cache::cache_command(action, global_opts),
             Commands::Version => {
                 println!("rig {}", env!("CARGO_PKG_VERSION"));
                 Ok(())
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use rig_core::config::Profiles;
+use rig_core::Config;
+use serde_json::json;
+use tracing::info;
+
+use crate::cli::{GlobalOpts, Session};
+use crate::commands::ProfileAction;
+
+pub async fn profile_command(action: &ProfileAction, global_opts: &GlobalOpts) -> Result<()> {
+    match action {
+        ProfileAction::List => list_profiles(global_opts),
+        ProfileAction::Use { name } => use_profile(name, global_opts),
+        ProfileAction::Remove { name } => remove_profile(name, global_opts),
+    }
+}
+
+fn list_profiles(global_opts: &GlobalOpts) -> Result<()> {
+    let config = global_opts.load_config()?;
+    let profiles = global_opts.load_profiles()?;
+    let active = profiles.resolve(None);
+
+    let rows: Vec<_> = profiles
+        .profiles
+        .iter()
+        .map(|(name, profile)| {
+            let session = Session {
+                config: config.clone(),
+                profile: name.clone(),
+                endpoint: profile.endpoint.clone(),
+            };
+            let logged_in = session.auth_client()?.is_authenticated();
+            Ok((name, &profile.endpoint, name == active, logged_in))
+        })
+        .collect::<Result<_>>()?;
+
+    if global_opts.is_json_output() {
+        let profiles: Vec<_> = rows
+            .iter()
+            .map(|(name, endpoint, active, logged_in)| {
+                json!({
+                    "name": name,
+                    "endpoint": endpoint,
+                    "active": active,
+                    "logged_in": logged_in,
+                })
+            })
+            .collect();
+        println!("{}", json!({ "profiles": profiles }));
+    } else if rows.is_empty() {
+        println!("No profiles yet; run 'rig login --profile <name>' to create one");
+    } else {
+        for (name, endpoint, active, logged_in) in rows {
+            let marker = if active { "*" } else { " " };
+            let state = if logged_in { "logged in" } else { "logged out" };
+            println!("{} {:<16} {:<40} {}", marker, name, endpoint, state);
+        }
+    }
+    Ok(())
+}
+
+fn use_profile(name: &str, global_opts: &GlobalOpts) -> Result<()> {
+    let mut profiles = global_opts.load_profiles()?;
+    profiles.set_active(name)?;
+    save(&profiles)?;
+
+    info!("Switched to profile '{}'", name);
+
+    if global_opts.is_json_output() {
+        println!("{}", json!({"status": "active", "profile": name}));
+    } else {
+        println!("✅ Now using profile '{}'", name);
+    }
+    Ok(())
+}
+
+fn remove_profile(name: &str, global_opts: &GlobalOpts) -> Result<()> {
+    let mut profiles = global_opts.load_profiles()?;
+    let profile = profiles.remove(name)?;
+
+    let session = Session {
+        config: global_opts.load_config()?,
+        profile: name.to_string(),
+        endpoint: profile.endpoint,
+    };
+    session.auth_client()?.logout()?;
+    save(&profiles)?;
+
+    info!("Removed profile '{}'", name);
+
+    if global_opts.is_json_output() {
+        println!("{}", json!({"status": "removed", "profile": name}));
+    } else {
+        println!("🗑️  Removed profile '{}'", name);
+    }
+    Ok(())
+}
+
+fn save(profiles: &Profiles) -> Result<()> {
+    profiles.save(&Config::profiles_path()?)
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use rig_core::http::{CreateApiToken, RequestOptions};
+use rig_utils::{format_table_row, format_table_separator, parse_duration};
+use serde_json::json;
+use tracing::info;
+
+use crate::cli::{GlobalOpts, ListArgs};
+use crate::commands::TokensAction;
+
+pub async fn tokens_command(action: &TokensAction, global_opts: &GlobalOpts) -> Result<()> {
+    match action {
+        TokensAction::Create {
+            name,
+            scopes,
+            ttl,
+            idempotency_key,
+        } => {
+            create_token(
+                name,
+                scopes,
+                ttl.as_deref(),
+                idempotency_key.as_deref(),
+                global_opts,
+            )
+            .await
+        }
+        TokensAction::List(list) => list_tokens(list, global_opts).await,
+        TokensAction::Revoke { id } => revoke_token(id, global_opts).await,
+    }
+}
+
+async fn create_token(
+    name: &str,
+    scopes: &[String],
+    ttl: Option<&str>,
+    idempotency_key: Option<&str>,
+    global_opts: &GlobalOpts,
+) -> Result<()> {
+    let request = CreateApiToken {
+        name: name.to_string(),
+        scopes: scopes.to_vec(),
+        ttl: ttl.map(parse_duration).transpose()?,
+    };
+
+    let mut options = RequestOptions::default();
+    if let Some(key) = idempotency_key {
+        options = options.idempotency_key(key);
+    }
+
+    let session = global_opts.session(None)?;
+    let created = global_opts
+        .api_client(&session)?
+        .create_api_token(&request, &options)
+        .await?;
+
+    info!(
+        "Created API token '{}' ({})",
+        created.token.name, created.token.id
+    );
+
+    let output = json!({
+        "id": created.token.id,
+        "name": created.token.name,
+        "scopes": created.token.scopes,
+        "expires_at": created.token.expires_at,
+        "secret": created.secret,
+    });
+
+    if global_opts.is_json_output() {
+        println!("{}", output);
+    } else if global_opts.is_yaml_output() {
+        print!("{}", serde_yaml::to_string(&output)?);
+    } else {
+        println!(
+            "🔑 Created API token '{}' ({})",
+            created.token.name, created.token.id
+        );
+        println!("   Scopes:  {}", created.token.scopes.join(", "));
+        if let Some(expires_at) = &created.token.expires_at {
+            println!("   Expires: {}", expires_at);
+        }
+        println!();
+        println!("{}", created.secret);
+        println!();
+        println!("⚠️  Copy the secret now; it will not be shown again");
+    }
+    Ok(())
+}
+
+async fn list_tokens(list: &ListArgs, global_opts: &GlobalOpts) -> Result<()> {
+    let session = global_opts.session(None)?;
+    let client = global_opts.api_client(&session)?;
+    let tokens = list
+        .collect(client.list_api_tokens(&list.options()))
+        .await?;
+
+    if global_opts.is_json_output() {
+        println!("{}", json!({ "tokens": tokens }));
+    } else if global_opts.is_yaml_output() {
+        print!("{}", serde_yaml::to_string(&json!({ "tokens": tokens }))?);
+    } else if tokens.is_empty() {
+        println!("No API tokens");
+    } else {
+        let widths = [20, 20, 30, 25];
+        println!(
+            "{}",
+            format_table_row(&["ID", "NAME", "SCOPES", "EXPIRES"], &widths)
+        );
+        println!("{}", format_table_separator(&widths));
+        for token in &tokens {
+            let scopes = token.scopes.join(",");
+            let expires = token.expires_at.as_deref().unwrap_or("never");
+            println!(
+                "{}",
+                format_table_row(&[&token.id, &token.name, &scopes, expires], &widths)
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn revoke_token(id: &str, global_opts: &GlobalOpts) -> Result<()> {
+    let session = global_opts.session(None)?;
+    global_opts
+        .api_client(&session)?
+        .revoke_api_token(id)
+        .await?;
+
+    info!("Revoked API token {}", id);
+
+    if global_opts.is_json_output() {
+        println!("{}", json!({"status": "revoked", "id": id}));
+    } else {
+        println!("🗑️  Revoked API token {}", id);
+    }
+    Ok(())
+}
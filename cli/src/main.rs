@@ -1,5 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
+use rig_core::RigError;
+use serde_json::json;
+use std::process::ExitCode;
 use tracing::info;
 use tracing_subscriber::{fmt, EnvFilter};
 
@@ -9,9 +12,19 @@ mod commands;
 use cli::{Cli, GlobalOpts};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report_error(&e, &cli.global);
+            exit_code(&e)
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<()> {
     // Initialize logging
     init_logging(&cli.global)?;
 
@@ -28,7 +41,9 @@ fn init_logging(opts: &GlobalOpts) -> Result<()> {
         EnvFilter::new("info")
     };
 
+    // Logs go to stderr so command output on stdout stays pipeable
     let subscriber = fmt::Subscriber::builder()
+        .with_writer(std::io::stderr)
         .with_env_filter(filter)
         .with_target(false)
         .with_thread_ids(false)
@@ -43,3 +58,38 @@ fn init_logging(opts: &GlobalOpts) -> Result<()> {
 
     Ok(())
 }
+
+/// Prints `error` to stderr, as an object scripts can parse under `--json`
+fn report_error(error: &anyhow::Error, opts: &GlobalOpts) {
+    if !opts.is_json_output() {
+        eprintln!("Error: {error:?}");
+        return;
+    }
+
+    let rig_error = error.downcast_ref::<RigError>();
+    let mut output = json!({
+        "kind": rig_error.map_or("error", RigError::kind),
+        "message": error.to_string(),
+        "request_id": rig_error.and_then(RigError::request_id),
+    });
+    if let Some(RigError::Validation { fields, .. }) = rig_error {
+        output["fields"] = fields
+            .iter()
+            .map(|field| json!({"field": field.field, "message": field.message}))
+            .collect();
+    }
+    eprintln!("{}", json!({ "error": output }));
+}
+
+/// Exit codes scripts can branch on; see the readme
+fn exit_code(error: &anyhow::Error) -> ExitCode {
+    let code = match error.downcast_ref::<RigError>() {
+        Some(RigError::Auth(_) | RigError::Forbidden { .. }) => 3,
+        Some(RigError::NotFound { .. }) => 4,
+        Some(RigError::Conflict { .. }) => 5,
+        Some(RigError::Validation { .. }) => 6,
+        Some(RigError::RateLimited { .. }) => 7,
+        _ => 1,
+    };
+    ExitCode::from(code)
+}
@@ -0,0 +1,511 @@
+use futures_util::Stream;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::http::{ListOptions, RequestOptions};
+use crate::{HttpClient, Result};
+
+mod models;
+
+pub use models::{
+    App, CreateApp, CreateDeployment, CreateNetwork, Deployment, DeploymentStatus, EnvVar,
+    Instance, Network, Secret, UpdateApp,
+};
+
+/// Typed access to the Max resource API
+///
+/// Apps live in a network, and variables, secrets, deployments and instances
+/// in an app, so their methods take the names of both. Lists are streamed
+/// page by page, see [`HttpClient::paginate`]. Requests are
+/// authenticated by the token provider of the underlying [`HttpClient`].
+#[derive(Debug, Clone)]
+pub struct MaxApi {
+    http: HttpClient,
+}
+
+impl MaxApi {
+    pub fn new(http: HttpClient) -> Self {
+        Self { http }
+    }
+
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    pub fn list_networks<'a>(
+        &'a self,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<Network>> + Send + 'a {
+        self.http
+            .paginate_endpoint(&["networks"], "networks", options)
+    }
+
+    pub async fn get_network(&self, network: &str) -> Result<Network> {
+        self.get(&["networks", network], "Loading the network")
+            .await
+    }
+
+    pub async fn create_network(
+        &self,
+        request: &CreateNetwork,
+        options: &RequestOptions,
+    ) -> Result<Network> {
+        self.create(&["networks"], request, options, "Creating the network")
+            .await
+    }
+
+    pub async fn delete_network(&self, network: &str) -> Result<()> {
+        self.delete(&["networks", network], "Deleting the network")
+            .await
+    }
+
+    pub fn list_apps<'a>(
+        &'a self,
+        network: &str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<App>> + Send + 'a {
+        self.http
+            .paginate_endpoint(&["networks", network, "apps"], "apps", options)
+    }
+
+    pub async fn get_app(&self, network: &str, app: &str) -> Result<App> {
+        self.get(&["networks", network, "apps", app], "Loading the app")
+            .await
+    }
+
+    pub async fn create_app(
+        &self,
+        network: &str,
+        request: &CreateApp,
+        options: &RequestOptions,
+    ) -> Result<App> {
+        self.create(
+            &["networks", network, "apps"],
+            request,
+            options,
+            "Creating the app",
+        )
+        .await
+    }
+
+    pub async fn update_app(&self, network: &str, app: &str, request: &UpdateApp) -> Result<App> {
+        self.send(
+            Method::PATCH,
+            &["networks", network, "apps", app],
+            request,
+            "Updating the app",
+        )
+        .await
+    }
+
+    pub async fn delete_app(&self, network: &str, app: &str) -> Result<()> {
+        self.delete(&["networks", network, "apps", app], "Deleting the app")
+            .await
+    }
+
+    pub fn list_vars<'a>(
+        &'a self,
+        network: &str,
+        app: &str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<EnvVar>> + Send + 'a {
+        self.http
+            .paginate_endpoint(&["networks", network, "apps", app, "vars"], "vars", options)
+    }
+
+    pub async fn get_var(&self, network: &str, app: &str, key: &str) -> Result<EnvVar> {
+        self.get(
+            &["networks", network, "apps", app, "vars", key],
+            "Loading the variable",
+        )
+        .await
+    }
+
+    /// Creates or replaces the variable `key`
+    pub async fn set_var(
+        &self,
+        network: &str,
+        app: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<EnvVar> {
+        self.send(
+            Method::PUT,
+            &["networks", network, "apps", app, "vars", key],
+            &json!({ "value": value }),
+            "Setting the variable",
+        )
+        .await
+    }
+
+    pub async fn delete_var(&self, network: &str, app: &str, key: &str) -> Result<()> {
+        self.delete(
+            &["networks", network, "apps", app, "vars", key],
+            "Deleting the variable",
+        )
+        .await
+    }
+
+    pub fn list_secrets<'a>(
+        &'a self,
+        network: &str,
+        app: &str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<Secret>> + Send + 'a {
+        self.http.paginate_endpoint(
+            &["networks", network, "apps", app, "secrets"],
+            "secrets",
+            options,
+        )
+    }
+
+    /// Creates or replaces the secret `name`
+    pub async fn set_secret(
+        &self,
+        network: &str,
+        app: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<Secret> {
+        self.send(
+            Method::PUT,
+            &["networks", network, "apps", app, "secrets", name],
+            &json!({ "value": value }),
+            "Setting the secret",
+        )
+        .await
+    }
+
+    pub async fn delete_secret(&self, network: &str, app: &str, name: &str) -> Result<()> {
+        self.delete(
+            &["networks", network, "apps", app, "secrets", name],
+            "Deleting the secret",
+        )
+        .await
+    }
+
+    pub fn list_deployments<'a>(
+        &'a self,
+        network: &str,
+        app: &str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<Deployment>> + Send + 'a {
+        self.http.paginate_endpoint(
+            &["networks", network, "apps", app, "deployments"],
+            "deployments",
+            options,
+        )
+    }
+
+    pub async fn get_deployment(&self, network: &str, app: &str, id: &str) -> Result<Deployment> {
+        self.get(
+            &["networks", network, "apps", app, "deployments", id],
+            "Loading the deployment",
+        )
+        .await
+    }
+
+    pub async fn create_deployment(
+        &self,
+        network: &str,
+        app: &str,
+        request: &CreateDeployment,
+        options: &RequestOptions,
+    ) -> Result<Deployment> {
+        self.create(
+            &["networks", network, "apps", app, "deployments"],
+            request,
+            options,
+            "Creating the deployment",
+        )
+        .await
+    }
+
+    pub async fn cancel_deployment(
+        &self,
+        network: &str,
+        app: &str,
+        id: &str,
+    ) -> Result<Deployment> {
+        self.send(
+            Method::POST,
+            &[
+                "networks",
+                network,
+                "apps",
+                app,
+                "deployments",
+                id,
+                "cancel",
+            ],
+            &json!({}),
+            "Cancelling the deployment",
+        )
+        .await
+    }
+
+    pub fn list_instances<'a>(
+        &'a self,
+        network: &str,
+        app: &str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<Instance>> + Send + 'a {
+        self.http.paginate_endpoint(
+            &["networks", network, "apps", app, "instances"],
+            "instances",
+            options,
+        )
+    }
+
+    pub async fn get_instance(&self, network: &str, app: &str, id: &str) -> Result<Instance> {
+        self.get(
+            &["networks", network, "apps", app, "instances", id],
+            "Loading the instance",
+        )
+        .await
+    }
+
+    /// Stops the instance; Max starts a replacement while the app is scaled
+    /// above zero
+    pub async fn delete_instance(&self, network: &str, app: &str, id: &str) -> Result<()> {
+        self.delete(
+            &["networks", network, "apps", app, "instances", id],
+            "Stopping the instance",
+        )
+        .await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, segments: &[&str], what: &str) -> Result<T> {
+        let url = self.http.endpoint(segments)?;
+        let response = self.http.send(self.http.request(Method::GET, url)).await?;
+        Ok(self.http.check(response, what).await?.json().await?)
+    }
+
+    async fn send<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: &B,
+        what: &str,
+    ) -> Result<T> {
+        self.send_with(method, segments, body, &RequestOptions::default(), what)
+            .await
+    }
+
+    /// POSTs a create; `options` may carry the caller's idempotency key
+    async fn create<B: Serialize, T: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        body: &B,
+        options: &RequestOptions,
+        what: &str,
+    ) -> Result<T> {
+        self.send_with(Method::POST, segments, body, options, what)
+            .await
+    }
+
+    async fn send_with<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        segments: &[&str],
+        body: &B,
+        options: &RequestOptions,
+        what: &str,
+    ) -> Result<T> {
+        let url = self.http.endpoint(segments)?;
+        // POSTs are keyed so a retried timeout cannot create a duplicate
+        let options = self.http.keyed(&method, options);
+        let response = self
+            .http
+            .execute(self.http.request(method, url).json(body), &options)
+            .await?;
+        Ok(self.http.check(response, what).await?.json().await?)
+    }
+
+    async fn delete(&self, segments: &[&str], what: &str) -> Result<()> {
+        let url = self.http.endpoint(segments)?;
+        let response = self
+            .http
+            .send(self.http.request(Method::DELETE, url))
+            .await?;
+        self.http.check(response, what).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RigError;
+    use futures_util::TryStreamExt;
+    use serde_json::Value;
+    use std::time::Duration;
+    use wiremock::matchers::{body_json, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn api(server: &MockServer) -> MaxApi {
+        MaxApi::new(HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap())
+    }
+
+    fn app_json(name: &str) -> Value {
+        json!({"id": format!("app_{name}"), "name": name, "network": "prod", "instances": 2})
+    }
+
+    #[tokio::test]
+    async fn test_base_url_path_is_kept() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/max/api/networks/prod"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"id": "net_1", "name": "prod"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = HttpClient::new(&format!("{}/max/", server.uri()), Duration::from_secs(5), 0);
+        let network = MaxApi::new(http.unwrap())
+            .get_network("prod")
+            .await
+            .unwrap();
+        assert_eq!(network.name, "prod");
+    }
+
+    #[tokio::test]
+    async fn test_base_url_path_is_kept_for_auth() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/max/auth/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"token": "t"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/max/auth/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"username": "ada"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/max/oauth/device/code"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "device_code": "dev",
+                "user_code": "ABCD-EFGH",
+                "verification_uri": "https://max.dev/device",
+                "expires_in": 600,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http =
+            HttpClient::new(&format!("{}/max/", server.uri()), Duration::from_secs(5), 0).unwrap();
+        let auth = http.authenticate("ada", "secret").await.unwrap();
+        let identity = http.identity(&auth.token).await.unwrap().unwrap();
+        assert_eq!(identity.username, "ada");
+        http.request_device_code("rig-cli").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_app_crud() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/networks/prod/apps"))
+            .and(body_json(json!({"name": "web", "instances": 2})))
+            .and(header_exists("idempotency-key"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(app_json("web")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/networks/prod/apps"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"apps": [app_json("web"), app_json("api")]})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/api/networks/prod/apps/web"))
+            .and(body_json(json!({"instances": 3})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(app_json("web")))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/api/networks/prod/apps/web"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let api = api(&server);
+        let created = api
+            .create_app(
+                "prod",
+                &CreateApp {
+                    name: "web".to_string(),
+                    instances: Some(2),
+                    port: None,
+                },
+                &RequestOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.id, "app_web");
+        assert_eq!(created.instances, Some(2));
+
+        let apps: Vec<App> = api
+            .list_apps("prod", &ListOptions::default())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[1].name, "api");
+
+        let update = UpdateApp {
+            instances: Some(3),
+            ..UpdateApp::default()
+        };
+        api.update_app("prod", "web", &update).await.unwrap();
+        api.delete_app("prod", "web").await.unwrap();
+
+        assert!(matches!(
+            api.get_app("prod", "missing").await,
+            Err(RigError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_vars_and_deployments() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/api/networks/prod/apps/web/vars/DATABASE%20URL"))
+            .and(body_json(json!({"value": "postgres://db"})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"key": "DATABASE URL", "value": "postgres://db"})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/networks/prod/apps/web/deployments/dep_1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "dep_1",
+                "app": "web",
+                "status": "rolling_back",
+            })))
+            .mount(&server)
+            .await;
+
+        let api = api(&server);
+        let var = api
+            .set_var("prod", "web", "DATABASE URL", "postgres://db")
+            .await
+            .unwrap();
+        assert_eq!(var.value, "postgres://db");
+
+        let deployment = api.get_deployment("prod", "web", "dep_1").await.unwrap();
+        assert_eq!(deployment.status, DeploymentStatus::Unknown);
+        assert!(!deployment.status.is_finished());
+    }
+}
@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use super::{now, AuthClient, AuthToken};
+use crate::http::{DeviceCode, DevicePoll};
+use crate::{Result, RigError};
+
+/// The OAuth client id rig identifies itself with
+const DEVICE_CLIENT_ID: &str = "rig-cli";
+/// Poll interval when the server does not suggest one (RFC 8628 §3.2)
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+/// How much a `slow_down` response grows the interval (RFC 8628 §3.5)
+const SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+
+impl AuthClient {
+    /// Logs in with the OAuth device authorization flow
+    ///
+    /// `on_code` is called once with the code the user must enter at the
+    /// verification URL; the token is then polled for and stored like
+    /// [`AuthClient::login`] does.
+    pub async fn device_login<F>(&self, on_code: F) -> Result<()>
+    where
+        F: FnOnce(&DeviceCode),
+    {
+        let device_code = self
+            .http_client
+            .request_device_code(DEVICE_CLIENT_ID)
+            .await?;
+        on_code(&device_code);
+
+        let token = self.poll_device_token(&device_code).await?;
+        self.store_token(&token)
+    }
+
+    /// Polls until the user approves or denies `device_code`, or it expires
+    pub async fn poll_device_token(&self, device_code: &DeviceCode) -> Result<AuthToken> {
+        let deadline = Instant::now() + Duration::from_secs(device_code.expires_in);
+        let mut interval = device_code
+            .interval
+            .map_or(DEFAULT_INTERVAL, Duration::from_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err(RigError::auth(
+                    "The login request expired; run `rig login --device` again",
+                ));
+            }
+
+            let poll = self
+                .http_client
+                .poll_device_token(DEVICE_CLIENT_ID, &device_code.device_code)
+                .await?;
+            match poll {
+                DevicePoll::Pending => debug!("Waiting for the device login to be approved"),
+                DevicePoll::SlowDown => {
+                    interval += SLOW_DOWN_STEP;
+                    debug!("Server asked to slow down; polling every {:?}", interval);
+                }
+                DevicePoll::Approved(response) => {
+                    let mut token =
+                        AuthToken::new(response.access_token, None, response.refresh_token);
+                    if let Some(expires_in) = response.expires_in {
+                        token.expires_at = Some(now() + expires_in);
+                    }
+                    return Ok(token);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::MemoryStore;
+    use crate::HttpClient;
+    use serde_json::json;
+    use std::sync::Arc;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn device_code(interval: u64, expires_in: u64) -> DeviceCode {
+        DeviceCode {
+            device_code: "dev-123".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: "https://max.dev/device".to_string(),
+            verification_uri_complete: None,
+            expires_in,
+            interval: Some(interval),
+        }
+    }
+
+    async fn auth_client(server: &MockServer) -> AuthClient {
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap();
+        AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()))
+    }
+
+    fn oauth_error(error: &str) -> ResponseTemplate {
+        ResponseTemplate::new(400).set_body_json(json!({ "error": error }))
+    }
+
+    #[tokio::test]
+    async fn test_polls_until_approved() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .and(body_string_contains("device_code=dev-123"))
+            .respond_with(oauth_error("authorization_pending"))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "device-token",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+                "refresh_token": "refresh-token",
+            })))
+            .mount(&server)
+            .await;
+
+        let auth = auth_client(&server).await;
+        let token = auth.poll_device_token(&device_code(0, 60)).await.unwrap();
+
+        assert_eq!(token.token, "device-token");
+        assert_eq!(token.refresh_token.as_deref(), Some("refresh-token"));
+        assert!(!token.expires_within(Duration::from_secs(3000)));
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_slow_down_and_denial() {
+        // The grown poll interval passes on virtual time
+        tokio::time::pause();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .respond_with(oauth_error("slow_down"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .respond_with(oauth_error("access_denied"))
+            .mount(&server)
+            .await;
+
+        // While a request is in flight the runtime is idle, and paused time
+        // would jump straight to its timeout. A ticking timer makes it advance
+        // 1ms at a time instead, far short of an hour-long timeout.
+        tokio::spawn(async {
+            loop {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(3600), 0).unwrap();
+        let auth = AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()));
+        let started = tokio::time::Instant::now();
+        let err = auth
+            .poll_device_token(&device_code(0, 60))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, RigError::Auth(_)));
+        assert!(started.elapsed() >= SLOW_DOWN_STEP);
+    }
+
+    #[tokio::test]
+    async fn test_requests_device_code() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/device/code"))
+            .and(body_string_contains("client_id=rig-cli"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "device_code": "dev-123",
+                "user_code": "ABCD-EFGH",
+                "verification_uri": "https://max.dev/device",
+                "expires_in": 900,
+            })))
+            .mount(&server)
+            .await;
+
+        let auth = auth_client(&server).await;
+        let code = auth
+            .http_client
+            .request_device_code(DEVICE_CLIENT_ID)
+            .await
+            .unwrap();
+        assert_eq!(code.user_code, "ABCD-EFGH");
+        assert_eq!(code.interval, None);
+    }
+}
@@ -0,0 +1,210 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::debug;
+use url::Url;
+
+use super::store::CredentialStore;
+use crate::{Result, RigError};
+
+/// Credentials kept by an external command, such as a 1Password or Vault
+/// wrapper, using the `git credential` helper protocol
+///
+/// The command is run through the shell with `get`, `store` or `erase`
+/// appended. It reads `key=value` lines on stdin describing the credential
+/// (`protocol`, `host`, `path` and `username`, which is the profile), plus
+/// `password` for `store`. For `get` it prints the same format, of which only
+/// `password` is used; printing nothing means there is no credential.
+#[derive(Debug)]
+pub struct HelperStore {
+    command: String,
+}
+
+impl HelperStore {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+
+    fn run(&self, verb: &str, key: &str, secret: Option<&str>) -> Result<String> {
+        let mut input = describe(key)?;
+        if let Some(secret) = secret {
+            input.push(("password", secret.to_string()));
+        }
+        let mut stdin = String::new();
+        for (name, value) in input {
+            if value.contains('\n') {
+                return Err(RigError::credential_store(format!(
+                    "Credential {name} cannot contain a newline"
+                )));
+            }
+            stdin.push_str(&format!("{name}={value}\n"));
+        }
+        stdin.push('\n');
+
+        debug!("Running credential helper `{} {}`", self.command, verb);
+        let mut child = shell(&format!("{} {}", self.command, verb))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                RigError::credential_store(format!(
+                    "Could not run credential helper `{}`: {e}",
+                    self.command
+                ))
+            })?;
+
+        if let Some(mut pipe) = child.stdin.take() {
+            // A helper may exit without reading its input, e.g. for `erase`
+            let _ = pipe.write_all(stdin.as_bytes());
+        }
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            return Err(RigError::credential_store(format!(
+                "Credential helper `{} {}` failed ({}): {}",
+                self.command,
+                verb,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl CredentialStore for HelperStore {
+    fn name(&self) -> &'static str {
+        "helper"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let output = self.run("get", key, None)?;
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(name, _)| *name == "password")
+            .map(|(_, value)| value.to_string())
+            .filter(|value| !value.is_empty()))
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<()> {
+        self.run("store", key, Some(secret)).map(|_| ())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.run("erase", key, None).map(|_| ())
+    }
+}
+
+/// Splits a `profile@endpoint` credential key into helper attributes
+///
+/// Profile names may contain `@`, so the key is split at the last one.
+fn describe(key: &str) -> Result<Vec<(&'static str, String)>> {
+    let (profile, endpoint) = key
+        .rsplit_once('@')
+        .ok_or_else(|| RigError::credential_store(format!("Malformed credential key '{key}'")))?;
+    let url = Url::parse(endpoint)?;
+
+    let mut host = url.host_str().unwrap_or_default().to_string();
+    if let Some(port) = url.port() {
+        host.push_str(&format!(":{port}"));
+    }
+
+    let mut attributes = vec![("protocol", url.scheme().to_string()), ("host", host)];
+    let path = url.path().trim_start_matches('/');
+    if !path.is_empty() {
+        attributes.push(("path", path.to_string()));
+    }
+    attributes.push(("username", profile.to_string()));
+    Ok(attributes)
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_key() {
+        assert_eq!(
+            describe("staging@https://staging.max.dev:8443/eu").unwrap(),
+            vec![
+                ("protocol", "https".to_string()),
+                ("host", "staging.max.dev:8443".to_string()),
+                ("path", "eu".to_string()),
+                ("username", "staging".to_string()),
+            ]
+        );
+        assert_eq!(
+            describe("ci@prod@https://api.max.dev").unwrap(),
+            vec![
+                ("protocol", "https".to_string()),
+                ("host", "api.max.dev".to_string()),
+                ("username", "ci@prod".to_string()),
+            ]
+        );
+        assert!(describe("no-endpoint").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_helper_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("helper.sh");
+        let vault = dir.path().join("vault");
+        // Keeps one credential per username in a file named after it
+        std::fs::write(
+            &script,
+            format!(
+                r#"vault={vault}
+input=$(cat)
+user=$(printf '%s\n' "$input" | sed -n 's/^username=//p')
+case "$1" in
+  get) [ -f "$vault.$user" ] && printf 'password=%s\n' "$(cat "$vault.$user")" ;;
+  store) printf '%s\n' "$input" | sed -n 's/^password=//p' > "$vault.$user" ;;
+  erase) rm -f "$vault.$user" ;;
+esac
+exit 0
+"#,
+                vault = vault.display()
+            ),
+        )
+        .unwrap();
+
+        let store = HelperStore::new(format!("sh {}", script.display()));
+        let key = "default@https://api.max.dev";
+
+        assert_eq!(store.get(key).unwrap(), None);
+        store.set(key, r#"{"token":"abc"}"#).unwrap();
+        assert_eq!(
+            store.get(key).unwrap().as_deref(),
+            Some(r#"{"token":"abc"}"#)
+        );
+        assert_eq!(store.get("other@https://api.max.dev").unwrap(), None);
+        store.delete(key).unwrap();
+        assert_eq!(store.get(key).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failing_helper_is_an_error() {
+        let store = HelperStore::new("echo 'vault is sealed' >&2; exit 1; true");
+        let err = store.get("default@https://api.max.dev").unwrap_err();
+        assert!(err.to_string().contains("vault is sealed"), "{err}");
+    }
+}
@@ -0,0 +1,148 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Claims read from a JWT access token
+///
+/// The signature is not verified: the claims are only hints for expiry and
+/// messages, and the server remains the authority on what a token may do.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Claims {
+    pub exp: Option<u64>,
+    pub sub: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawClaims {
+    #[serde(default)]
+    exp: Option<u64>,
+    #[serde(default)]
+    sub: Option<String>,
+    /// OAuth style, space separated
+    #[serde(default)]
+    scope: Option<String>,
+    #[serde(default, alias = "scp")]
+    scopes: Option<Value>,
+}
+
+/// Decodes the claims of `token`, or `None` if it is not a JWT
+pub fn decode_claims(token: &str) -> Option<Claims> {
+    let mut parts = token.split('.');
+    let (Some(_header), Some(payload), Some(_signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let raw: RawClaims = serde_json::from_slice(&payload).ok()?;
+
+    let mut scopes: Vec<String> = raw
+        .scope
+        .iter()
+        .flat_map(|scope| scope.split_whitespace())
+        .map(str::to_string)
+        .collect();
+    match raw.scopes {
+        Some(Value::String(list)) => scopes.extend(list.split_whitespace().map(str::to_string)),
+        Some(Value::Array(items)) => scopes.extend(
+            items
+                .into_iter()
+                .filter_map(|item| item.as_str().map(str::to_string)),
+        ),
+        _ => {}
+    }
+
+    Some(Claims {
+        exp: raw.exp,
+        sub: raw.sub,
+        scopes,
+    })
+}
+
+/// Parses an `expires_at` value sent as epoch seconds or an RFC 3339 time
+pub fn parse_expires_at(expires_at: &str) -> Option<u64> {
+    let expires_at = expires_at.trim();
+    expires_at.parse::<u64>().ok().or_else(|| {
+        DateTime::parse_from_rfc3339(expires_at)
+            .ok()
+            .and_then(|time| u64::try_from(time.timestamp()).ok())
+    })
+}
+
+/// Describes who `token` belongs to and what it may do, for error messages
+pub fn describe_token(token: &str) -> Option<String> {
+    let claims = decode_claims(token)?;
+    let subject = claims.sub.as_deref().unwrap_or("an unknown user");
+    Some(if claims.scopes.is_empty() {
+        format!("signed in as {subject}")
+    } else {
+        format!(
+            "signed in as {subject} with scopes {}",
+            claims.scopes.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn encode_for_test(claims: serde_json::Value) -> String {
+    let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+    format!("{header}.{payload}.signature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_claims() {
+        let token = encode_for_test(json!({
+            "sub": "ada",
+            "exp": 1_900_000_000u64,
+            "scope": "apps:read networks:write",
+        }));
+        assert_eq!(
+            decode_claims(&token),
+            Some(Claims {
+                exp: Some(1_900_000_000),
+                sub: Some("ada".to_string()),
+                scopes: vec!["apps:read".to_string(), "networks:write".to_string()],
+            })
+        );
+
+        let token = encode_for_test(json!({"scopes": ["apps:read"]}));
+        assert_eq!(decode_claims(&token).unwrap().scopes, ["apps:read"]);
+
+        assert_eq!(decode_claims("opaque-token"), None);
+        assert_eq!(decode_claims("a.!!!.c"), None);
+    }
+
+    #[test]
+    fn test_parse_expires_at() {
+        assert_eq!(parse_expires_at("1700000000"), Some(1_700_000_000));
+        assert_eq!(
+            parse_expires_at("2023-11-14T22:13:20Z"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            parse_expires_at("2023-11-15T00:13:20+02:00"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(parse_expires_at("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_describe_token() {
+        let token = encode_for_test(json!({"sub": "ada", "scope": "apps:read"}));
+        assert_eq!(
+            describe_token(&token).as_deref(),
+            Some("signed in as ada with scopes apps:read")
+        );
+        assert_eq!(describe_token("opaque"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+
+use crate::{Result, RigError};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A lock file held while a token is refreshed, so that concurrent rig
+/// processes don't each spend the same refresh token
+///
+/// The lock is released when dropped. A lock older than `stale_after` is
+/// assumed to belong to a process that died mid-refresh and is taken over.
+#[derive(Debug)]
+pub(crate) struct RefreshLock {
+    path: PathBuf,
+}
+
+impl RefreshLock {
+    pub(crate) async fn acquire(
+        path: PathBuf,
+        timeout: Duration,
+        stale_after: Duration,
+    ) -> Result<Self> {
+        let started = Instant::now();
+        loop {
+            if let Some(lock) = Self::try_acquire(&path, stale_after)? {
+                return Ok(lock);
+            }
+            if started.elapsed() >= timeout {
+                return Err(RigError::auth(
+                    "Timed out waiting for another rig process to refresh the token",
+                ));
+            }
+
+            debug!("Waiting for token refresh lock {}", path.display());
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// [`RefreshLock::acquire`] for synchronous callers, e.g. credential
+    /// stores guarding a read-modify-write of their file
+    pub(crate) fn acquire_blocking(
+        path: PathBuf,
+        timeout: Duration,
+        stale_after: Duration,
+    ) -> Result<Self> {
+        let started = Instant::now();
+        loop {
+            if let Some(lock) = Self::try_acquire(&path, stale_after)? {
+                return Ok(lock);
+            }
+            if started.elapsed() >= timeout {
+                return Err(RigError::credential_store(format!(
+                    "Timed out waiting for another rig process to release {}",
+                    path.display()
+                )));
+            }
+
+            debug!("Waiting for lock {}", path.display());
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Takes the lock if it is free or stale
+    fn try_acquire(path: &Path, stale_after: Duration) -> Result<Option<Self>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => {
+                    return Ok(Some(Self {
+                        path: path.to_path_buf(),
+                    }))
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            if !is_stale(path, stale_after) {
+                return Ok(None);
+            }
+            warn!("Removing stale lock {}", path.display());
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for RefreshLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn is_stale(path: &Path, stale_after: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= stale_after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locks").join("default.lock");
+        let long = Duration::from_secs(60);
+
+        let lock = RefreshLock::acquire(path.clone(), long, long)
+            .await
+            .unwrap();
+        let contended = RefreshLock::acquire(path.clone(), Duration::from_millis(250), long).await;
+        assert!(matches!(contended, Err(RigError::Auth(_))));
+
+        drop(lock);
+        assert!(!path.exists());
+        RefreshLock::acquire(path.clone(), long, long)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stale_lock_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default.lock");
+        fs::write(&path, "").unwrap();
+
+        let lock = RefreshLock::acquire(path, Duration::from_secs(1), Duration::ZERO).await;
+        assert!(lock.is_ok());
+    }
+}
@@ -1,74 +1,278 @@
-use keyring::Entry;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use url::Url;
 
+use crate::config::{Config, CredentialStoreKind, DEFAULT_PROFILE};
+use crate::http::Identity;
 use crate::{HttpClient, Result, RigError};
 
-const KEYRING_SERVICE: &str = "rig-cli";
-const KEYRING_USER: &str = "default";
+mod device;
+mod helper;
+mod jwt;
+mod lock;
+mod store;
+
+pub use helper::HelperStore;
+pub use jwt::{decode_claims, describe_token, parse_expires_at, Claims};
+use lock::RefreshLock;
+pub use store::{
+    configured_store, migrate_credentials, open_store, CredentialStore, FileStore, KeyringStore,
+    MemoryStore,
+};
+
+/// Tokens are renewed this long before they expire
+const RENEW_BEFORE: Duration = Duration::from_secs(60);
+const LOCK_TIMEOUT: Duration = Duration::from_secs(15);
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(60);
+/// Where rig kept the token before credentials were keyed by profile and
+/// endpoint; in the keyring this is the `rig-cli`/`default` entry
+const LEGACY_KEY: &str = "default";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthToken {
     pub token: String,
     pub expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+impl AuthToken {
+    /// Builds a token from a server response, taking the expiry from
+    /// `expires_at` or, failing that, from the token's JWT claims
+    pub fn new(token: String, expires_at: Option<&str>, refresh_token: Option<String>) -> Self {
+        let expires_at = expires_at
+            .and_then(parse_expires_at)
+            .or_else(|| decode_claims(&token)?.exp);
+        Self {
+            token,
+            expires_at,
+            refresh_token,
+        }
+    }
+
+    /// The claims of the token, if it is a JWT
+    pub fn claims(&self) -> Option<Claims> {
+        decode_claims(&self.token)
+    }
+
+    /// When the token expires, falling back to its JWT `exp` claim
+    pub fn expiry(&self) -> Option<u64> {
+        self.expires_at.or_else(|| self.claims()?.exp)
+    }
+
+    /// Whether the token expires within `window` from now
+    pub fn expires_within(&self, window: Duration) -> bool {
+        self.expiry()
+            .is_some_and(|expires_at| now() + window.as_secs() >= expires_at)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::ZERO)
+    }
+
+    /// Time left until the token expires, if it has an expiry
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expiry()
+            .map(|expires_at| Duration::from_secs(expires_at.saturating_sub(now())))
+    }
+}
+
+/// Supplies the bearer token used to authenticate with Max
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// The current token, if logged in
+    async fn token(&self) -> Result<Option<String>>;
+
+    /// Called after the server rejected `rejected`; returns a replacement
+    /// token when one can be obtained
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>>;
 }
 
 #[derive(Debug)]
 pub struct AuthClient {
     http_client: HttpClient,
-    keyring: Entry,
+    profile: String,
+    store: Arc<dyn CredentialStore>,
+    key: String,
+    lock_path: Option<PathBuf>,
+    token: Option<String>,
 }
 
 impl AuthClient {
     pub fn new(http_client: HttpClient) -> Result<Self> {
-        let keyring = Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+        Self::for_profile(http_client, DEFAULT_PROFILE)
+    }
+
+    /// Creates a client whose credentials are keyed by `profile` and the
+    /// client's endpoint, in the automatically selected store
+    pub fn for_profile(http_client: HttpClient, profile: &str) -> Result<Self> {
+        let store = open_store(CredentialStoreKind::Auto)?;
+        Ok(Self::with_store(http_client, profile, store))
+    }
+
+    /// Creates a client for `profile` that keeps its credentials in `store`
+    pub fn with_store(
+        http_client: HttpClient,
+        profile: &str,
+        store: Arc<dyn CredentialStore>,
+    ) -> Self {
+        let key = credential_key(profile, http_client.base_url());
+        let lock_path = Config::config_dir()
+            .ok()
+            .map(|dir| dir.join("locks").join(lock_file_name(&key)));
 
-        Ok(Self {
+        Self {
             http_client,
-            keyring,
-        })
+            profile: profile.to_string(),
+            store,
+            key,
+            lock_path,
+            token: None,
+        }
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Uses `token` ahead of the credential store; it is kept in memory only and
+    /// never persisted
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Whether the token comes from [`AuthClient::with_token`] rather than
+    /// the credential store
+    pub fn has_token_override(&self) -> bool {
+        self.token.is_some()
     }
 
     pub async fn login(&self, username: &str, password: &str) -> Result<()> {
         let auth_response = self.http_client.authenticate(username, password).await?;
 
-        let expires_at = auth_response
-            .expires_at
-            .and_then(|exp| exp.parse::<u64>().ok());
-
-        let token = AuthToken {
-            token: auth_response.token,
-            expires_at,
-        };
+        let token = AuthToken::new(
+            auth_response.token,
+            auth_response.expires_at.as_deref(),
+            auth_response.refresh_token,
+        );
 
         self.store_token(&token)?;
         Ok(())
     }
 
+    /// The stored token, or `None` once it has expired
+    ///
+    /// The expiry comes from `expires_at` or the token's JWT claims. An
+    /// expired stored token without a refresh token is removed. Use
+    /// [`AuthClient::valid_token`] to renew tokens that are about to expire.
     pub fn get_token(&self) -> Result<Option<AuthToken>> {
-        match self.keyring.get_password() {
-            Ok(token_json) => {
-                let token: AuthToken = serde_json::from_str(&token_json)?;
-
-                // Check if token is expired
-                if let Some(expires_at) = token.expires_at {
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs();
-
-                    if now >= expires_at {
-                        // Token expired, remove it
-                        self.logout()?;
-                        return Ok(None);
-                    }
-                }
-
-                Ok(Some(token))
+        let Some(token) = self.stored_token()? else {
+            return Ok(None);
+        };
+
+        if token.is_expired() {
+            if self.has_token_override() {
+                warn!("The provided token has expired");
+            } else if token.refresh_token.is_none() {
+                self.logout()?;
+            }
+            return Ok(None);
+        }
+
+        Ok(Some(token))
+    }
+
+    /// The stored token, renewed first if it expires within a minute and a
+    /// refresh token is available
+    pub async fn valid_token(&self) -> Result<Option<AuthToken>> {
+        match self.stored_token()? {
+            Some(token) if token.refresh_token.is_some() && token.expires_within(RENEW_BEFORE) => {
+                self.renew(&token.token).await
+            }
+            _ => self.get_token(),
+        }
+    }
+
+    /// Exchanges the refresh token for a new token, unless another process
+    /// already replaced `stale` while we waited for the lock
+    async fn renew(&self, stale: &str) -> Result<Option<AuthToken>> {
+        let _lock = match &self.lock_path {
+            Some(path) => {
+                Some(RefreshLock::acquire(path.clone(), LOCK_TIMEOUT, LOCK_STALE_AFTER).await?)
+            }
+            None => None,
+        };
+
+        let Some(current) = self.stored_token()? else {
+            return Ok(None);
+        };
+        if current.token != stale && !current.expires_within(RENEW_BEFORE) {
+            debug!("Token was renewed by another process");
+            return Ok(Some(current));
+        }
+        let Some(refresh_token) = current.refresh_token.clone() else {
+            return self.get_token();
+        };
+
+        debug!("Renewing token for profile '{}'", self.profile);
+        let auth_response = match self.http_client.refresh(&refresh_token).await {
+            Ok(auth_response) => auth_response,
+            Err(RigError::Auth(reason)) => {
+                warn!("{}", reason);
+                self.logout()?;
+                return Ok(None);
             }
-            Err(keyring::Error::NoEntry) => Ok(None),
-            Err(e) => Err(RigError::from(e)),
+            // The server may be briefly unavailable; keep using the current
+            // token while it is still valid
+            Err(e) if !current.is_expired() => {
+                warn!("Token renewal failed: {}", e);
+                return Ok(Some(current));
+            }
+            Err(e) => return Err(e),
+        };
+
+        let token = AuthToken::new(
+            auth_response.token,
+            auth_response.expires_at.as_deref(),
+            auth_response.refresh_token.or(Some(refresh_token)),
+        );
+        self.store_token(&token)?;
+        Ok(Some(token))
+    }
+
+    /// Like [`AuthClient::valid_token`], but not being logged in is an error
+    pub async fn require_token(&self) -> Result<AuthToken> {
+        self.valid_token()
+            .await?
+            .ok_or_else(|| RigError::auth("Not logged in; run `rig login` first"))
+    }
+
+    /// Asks the server who the current token belongs to
+    ///
+    /// Servers without an identity endpoint are answered from the token's
+    /// JWT `sub` claim instead.
+    pub async fn whoami(&self) -> Result<Identity> {
+        let token = self.require_token().await?;
+
+        if let Some(identity) = self.http_client.identity(&token.token).await? {
+            return Ok(identity);
         }
+        let username = token
+            .claims()
+            .and_then(|claims| claims.sub)
+            .ok_or_else(|| {
+                RigError::generic("The server has no identity endpoint and the token names no user")
+            })?;
+        Ok(Identity {
+            username,
+            email: None,
+            org: None,
+        })
     }
 
     pub fn is_authenticated(&self) -> bool {
@@ -76,16 +280,274 @@ impl AuthClient {
     }
 
     pub fn logout(&self) -> Result<()> {
-        match self.keyring.delete_password() {
-            Ok(()) => Ok(()),
-            Err(keyring::Error::NoEntry) => Ok(()), // Already logged out
-            Err(e) => Err(RigError::from(e)),
+        self.store.delete(&self.key)
+    }
+
+    /// The token as stored, without expiry checks
+    fn stored_token(&self) -> Result<Option<AuthToken>> {
+        if let Some(token) = &self.token {
+            return Ok(Some(AuthToken::new(token.clone(), None, None)));
+        }
+
+        let token_json = match self.store.get(&self.key)? {
+            Some(token_json) => Some(token_json),
+            None => self.migrate_legacy_token()?,
+        };
+        match token_json {
+            Some(token_json) => Ok(Some(serde_json::from_str(&token_json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Moves a login saved by older versions of rig to the default profile's key
+    fn migrate_legacy_token(&self) -> Result<Option<String>> {
+        if self.profile != DEFAULT_PROFILE {
+            return Ok(None);
         }
+        let Some(token_json) = self.store.get(LEGACY_KEY)? else {
+            return Ok(None);
+        };
+
+        debug!("Moving the saved login to {}", self.key);
+        self.store.set(&self.key, &token_json)?;
+        self.store.delete(LEGACY_KEY)?;
+        Ok(Some(token_json))
     }
 
-    fn store_token(&self, token: &AuthToken) -> Result<()> {
+    pub(crate) fn store_token(&self, token: &AuthToken) -> Result<()> {
         let token_json = serde_json::to_string(token)?;
-        self.keyring.set_password(&token_json)?;
-        Ok(())
+        self.store.set(&self.key, &token_json)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// A file name for the lock guarding the credentials under `key`
+fn lock_file_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{name}.lock")
+}
+
+/// The credential store key for `profile` on `endpoint`
+pub fn credential_key(profile: &str, endpoint: &Url) -> String {
+    let endpoint = endpoint.as_str().trim_end_matches('/');
+    format!("{profile}@{endpoint}")
+}
+
+#[async_trait]
+impl TokenProvider for AuthClient {
+    async fn token(&self) -> Result<Option<String>> {
+        Ok(self.valid_token().await?.map(|token| token.token))
+    }
+
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>> {
+        let Some(current) = self.stored_token()? else {
+            return Ok(None);
+        };
+
+        // Another rig process may have logged in again since we read the token
+        let token = if current.token != rejected {
+            self.get_token()?
+        } else if current.refresh_token.is_some() {
+            self.renew(rejected).await?
+        } else {
+            None
+        };
+        Ok(token
+            .map(|token| token.token)
+            .filter(|token| token != rejected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_credential_key_is_keyed_by_profile_and_endpoint() {
+        let endpoint = Url::parse("https://staging.max.dev").unwrap();
+        assert_eq!(
+            credential_key("staging", &endpoint),
+            "staging@https://staging.max.dev"
+        );
+    }
+
+    #[test]
+    fn test_token_expiry_window() {
+        let token = AuthToken {
+            token: "t".to_string(),
+            expires_at: Some(now() + 30),
+            refresh_token: None,
+        };
+        assert!(!token.is_expired());
+        assert!(token.expires_within(RENEW_BEFORE));
+
+        let never = AuthToken {
+            expires_at: None,
+            ..token
+        };
+        assert!(!never.expires_within(RENEW_BEFORE));
+    }
+
+    #[test]
+    fn test_lock_file_name() {
+        assert_eq!(
+            lock_file_name("staging@https://staging.max.dev"),
+            "staging_https___staging_max_dev.lock"
+        );
+    }
+
+    #[test]
+    fn test_expiry_from_rfc3339_or_jwt_claims() {
+        let token = AuthToken::new("opaque".to_string(), Some("2023-11-14T22:13:20Z"), None);
+        assert_eq!(token.expiry(), Some(1_700_000_000));
+        assert!(token.is_expired());
+
+        let jwt = jwt::encode_for_test(json!({"sub": "ada", "exp": now() + 600}));
+        let token = AuthToken::new(jwt, None, None);
+        assert_eq!(token.expiry(), Some(now() + 600));
+        assert!(!token.expires_within(RENEW_BEFORE));
+        assert_eq!(token.claims().unwrap().sub.as_deref(), Some("ada"));
+    }
+
+    #[test]
+    fn test_legacy_login_moves_to_the_default_profile() {
+        let store = Arc::new(MemoryStore::default());
+        store
+            .set(LEGACY_KEY, r#"{"token":"old","expires_at":null}"#)
+            .unwrap();
+        let http_client =
+            HttpClient::new("https://api.max.dev", Duration::from_secs(1), 0).unwrap();
+
+        let staging = AuthClient::with_store(http_client.clone(), "staging", store.clone());
+        assert!(staging.get_token().unwrap().is_none());
+
+        let auth = AuthClient::with_store(http_client, DEFAULT_PROFILE, store.clone());
+        assert_eq!(auth.get_token().unwrap().unwrap().token, "old");
+        assert_eq!(store.get(LEGACY_KEY).unwrap(), None);
+        assert!(store.get(&auth.key).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_expired_provided_token_is_not_used() {
+        let store = Arc::new(MemoryStore::default());
+        let http_client = HttpClient::new("http://localhost", Duration::from_secs(1), 0).unwrap();
+        let stored = AuthClient::with_store(http_client.clone(), "default", store.clone());
+        stored
+            .store_token(&AuthToken::new("stored".to_string(), None, None))
+            .unwrap();
+
+        let jwt = jwt::encode_for_test(json!({"exp": now() - 60}));
+        let auth = AuthClient::with_store(http_client, "default", store).with_token(jwt);
+        assert!(auth.get_token().unwrap().is_none());
+        // The stored login is left alone
+        assert!(stored.get_token().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_renews_token_before_expiry() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/auth/refresh"))
+            .and(body_json(json!({"refresh_token": "refresh-1"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "token": "fresh",
+                "expires_at": (now() + 3600).to_string(),
+                "refresh_token": "refresh-2",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap();
+        let mut auth =
+            AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()));
+        auth.lock_path = Some(dir.path().join("default.lock"));
+        auth.store_token(&AuthToken {
+            token: "stale".to_string(),
+            expires_at: Some(now() + 10),
+            refresh_token: Some("refresh-1".to_string()),
+        })
+        .unwrap();
+
+        assert_eq!(auth.token().await.unwrap().as_deref(), Some("fresh"));
+        // The renewed token is stored, so the next call needs no refresh
+        let stored = auth.valid_token().await.unwrap().unwrap();
+        assert_eq!(stored.token, "fresh");
+        assert_eq!(stored.refresh_token.as_deref(), Some("refresh-2"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_refresh_token_logs_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/auth/refresh"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap();
+        let mut auth =
+            AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()));
+        auth.lock_path = Some(dir.path().join("default.lock"));
+        auth.store_token(&AuthToken {
+            token: "expired".to_string(),
+            expires_at: Some(now() - 10),
+            refresh_token: Some("revoked".to_string()),
+        })
+        .unwrap();
+
+        assert_eq!(auth.refresh("expired").await.unwrap(), None);
+        assert!(auth.stored_token().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_whoami_sends_bearer_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/auth/me"))
+            .and(header("authorization", "Bearer ci-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "username": "ada",
+                "org": "analytical-engines",
+            })))
+            .mount(&server)
+            .await;
+
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap();
+        let auth = AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()));
+        assert!(matches!(auth.whoami().await, Err(RigError::Auth(_))));
+
+        let identity = auth.with_token("ci-token").whoami().await.unwrap();
+        assert_eq!(identity.username, "ada");
+        assert_eq!(identity.org.as_deref(), Some("analytical-engines"));
+        assert_eq!(identity.email, None);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_token_is_used_ahead_of_store() {
+        let http_client = HttpClient::new("http://localhost", Duration::from_secs(1), 0).unwrap();
+        let auth = AuthClient::with_store(http_client, "default", Arc::new(MemoryStore::default()))
+            .with_token("ci-token");
+
+        assert!(auth.has_token_override());
+        assert_eq!(auth.get_token().unwrap().unwrap().token, "ci-token");
+        assert_eq!(auth.token().await.unwrap().as_deref(), Some("ci-token"));
+        // A rejected static token has no replacement
+        assert_eq!(auth.refresh("ci-token").await.unwrap(), None);
     }
 }
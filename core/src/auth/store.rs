@@ -0,0 +1,385 @@
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use keyring::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+use super::helper::HelperStore;
+use super::lock::RefreshLock;
+use crate::config::{AuthConfig, Config, CredentialStoreKind};
+use crate::files::{create_private, write_private};
+use crate::{Result, RigError};
+
+const KEYRING_SERVICE: &str = "rig-cli";
+const KEYRING_PROBE: &str = "rig-cli-probe";
+const CREDENTIALS_FILE: &str = "credentials.enc";
+const KEY_FILE: &str = "credentials.key";
+const LOCK_FILE: &str = "credentials.lock";
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(30);
+const NONCE_LEN: usize = 12;
+
+/// Somewhere to keep secrets, keyed by profile and endpoint
+pub trait CredentialStore: Debug + Send + Sync {
+    /// A short name for messages, e.g. `keyring`
+    fn name(&self) -> &'static str;
+
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    fn set(&self, key: &str, secret: &str) -> Result<()>;
+
+    /// Removes `key`; removing a missing key is not an error
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Opens the store the `[auth]` config selects: the credential helper when
+/// one is set, otherwise `credential_store`
+pub fn configured_store(config: &AuthConfig) -> Result<Arc<dyn CredentialStore>> {
+    match &config.credential_helper {
+        Some(command) => Ok(Arc::new(HelperStore::new(command.clone()))),
+        None => open_store(config.credential_store),
+    }
+}
+
+/// Opens the store selected by `kind`
+///
+/// `Auto` uses the OS keyring when a keyring service answers and falls back
+/// to the encrypted file otherwise, e.g. in containers without Secret Service.
+pub fn open_store(kind: CredentialStoreKind) -> Result<Arc<dyn CredentialStore>> {
+    match kind {
+        CredentialStoreKind::Keyring => Ok(Arc::new(KeyringStore)),
+        CredentialStoreKind::File => Ok(Arc::new(FileStore::new(credentials_dir()?))),
+        CredentialStoreKind::Memory => Ok(Arc::new(MemoryStore::default())),
+        CredentialStoreKind::Auto if KeyringStore::is_available() => Ok(Arc::new(KeyringStore)),
+        CredentialStoreKind::Auto => {
+            debug!("No keyring service available, using the encrypted credentials file");
+            Ok(Arc::new(FileStore::new(credentials_dir()?)))
+        }
+    }
+}
+
+/// Moves the secrets for `keys` from one store to another, returning the
+/// keys that were moved
+pub fn migrate_credentials(
+    from: &dyn CredentialStore,
+    to: &dyn CredentialStore,
+    keys: &[String],
+) -> Result<Vec<String>> {
+    let mut moved = Vec::new();
+    for key in keys {
+        let Some(secret) = from.get(key)? else {
+            continue;
+        };
+        to.set(key, &secret)?;
+        from.delete(key)?;
+        moved.push(key.clone());
+    }
+    Ok(moved)
+}
+
+fn credentials_dir() -> Result<PathBuf> {
+    Config::config_dir().map_err(|e| RigError::credential_store(e.to_string()))
+}
+
+/// The OS keyring (Secret Service, macOS Keychain, Windows Credential Manager)
+#[derive(Debug)]
+pub struct KeyringStore;
+
+impl KeyringStore {
+    /// Whether a keyring service is reachable
+    pub fn is_available() -> bool {
+        match Entry::new(KEYRING_SERVICE, KEYRING_PROBE).and_then(|entry| entry.get_password()) {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                debug!("Keyring unavailable: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl CredentialStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match Entry::new(KEYRING_SERVICE, key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(RigError::from(e)),
+        }
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<()> {
+        Entry::new(KEYRING_SERVICE, key)?.set_password(secret)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match Entry::new(KEYRING_SERVICE, key)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(RigError::from(e)),
+        }
+    }
+}
+
+/// Secrets encrypted with ChaCha20-Poly1305 in `credentials.enc`
+///
+/// The key lives next to it in `credentials.key`; both are only readable by
+/// the owner. This keeps tokens out of plain text in backups and logs, but
+/// is no stronger than the permissions on the config directory.
+///
+/// Every access holds `credentials.lock`, so rig processes updating
+/// different profiles at once don't drop each other's changes.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn lock(&self) -> Result<RefreshLock> {
+        RefreshLock::acquire_blocking(self.dir.join(LOCK_FILE), LOCK_TIMEOUT, LOCK_STALE_AFTER)
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, String>> {
+        let data = match fs::read(self.dir.join(CREDENTIALS_FILE)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if data.len() < NONCE_LEN {
+            return Err(RigError::credential_store(
+                "The credentials file is truncated",
+            ));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                RigError::credential_store(
+                    "Could not decrypt the credentials file; was the key file replaced?",
+                )
+            })?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    fn save(&self, secrets: &BTreeMap<String, String>) -> Result<()> {
+        let plaintext = serde_json::to_vec(secrets)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| RigError::credential_store("Could not encrypt credentials"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        write_private(&self.dir.join(CREDENTIALS_FILE), &data)
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305> {
+        let path = self.dir.join(KEY_FILE);
+        let key = match fs::read(&path) {
+            Ok(key) if key.len() == 32 => key,
+            Ok(_) => return Err(RigError::credential_store("The credentials key is invalid")),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                fs::create_dir_all(&self.dir)?;
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                match create_private(&path, &key) {
+                    Ok(()) => key.to_vec(),
+                    // Another process created it first; use theirs
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => return self.cipher(),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let _lock = self.lock()?;
+        Ok(self.load()?.remove(key))
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut secrets = self.load()?;
+        secrets.insert(key.to_string(), secret.to_string());
+        self.save(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut secrets = self.load()?;
+        if secrets.remove(key).is_some() {
+            self.save(&secrets)?;
+        }
+        Ok(())
+    }
+}
+
+/// Secrets held for the life of the process
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl CredentialStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.secrets.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, secret: &str) -> Result<()> {
+        self.secrets
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.secrets.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("rig"));
+
+        assert_eq!(store.get("default@https://api.max.dev").unwrap(), None);
+        store
+            .set("default@https://api.max.dev", "secret-token")
+            .unwrap();
+        store
+            .set("staging@https://staging.max.dev", "other")
+            .unwrap();
+
+        let reopened = FileStore::new(dir.path().join("rig"));
+        assert_eq!(
+            reopened
+                .get("default@https://api.max.dev")
+                .unwrap()
+                .as_deref(),
+            Some("secret-token")
+        );
+
+        let raw = fs::read(dir.path().join("rig").join(CREDENTIALS_FILE)).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("secret-token"));
+
+        reopened.delete("default@https://api.max.dev").unwrap();
+        reopened.delete("default@https://api.max.dev").unwrap();
+        assert_eq!(store.get("default@https://api.max.dev").unwrap(), None);
+        assert_eq!(
+            store
+                .get("staging@https://staging.max.dev")
+                .unwrap()
+                .as_deref(),
+            Some("other")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_store_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        FileStore::new(dir.path()).set("key", "secret").unwrap();
+
+        for file in [CREDENTIALS_FILE, KEY_FILE] {
+            let mode = fs::metadata(dir.path().join(file))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600, "{file}");
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writers_keep_each_others_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let dir = dir.path().to_path_buf();
+                std::thread::spawn(move || {
+                    FileStore::new(dir)
+                        .set(&format!("profile-{i}"), &format!("secret-{i}"))
+                        .unwrap()
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let store = FileStore::new(dir.path());
+        for i in 0..8 {
+            assert_eq!(
+                store.get(&format!("profile-{i}")).unwrap(),
+                Some(format!("secret-{i}"))
+            );
+        }
+        let leftovers = fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("tmp".as_ref()))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn test_wrong_key_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path());
+        store.set("key", "secret").unwrap();
+        fs::write(dir.path().join(KEY_FILE), [7u8; 32]).unwrap();
+
+        assert!(matches!(
+            store.get("key"),
+            Err(RigError::CredentialStore(_))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_between_stores() {
+        let from = MemoryStore::default();
+        let to = MemoryStore::default();
+        from.set("a", "1").unwrap();
+        from.set("b", "2").unwrap();
+
+        let keys = ["a".to_string(), "b".to_string(), "missing".to_string()];
+        let moved = migrate_credentials(&from, &to, &keys).unwrap();
+
+        assert_eq!(moved, ["a", "b"]);
+        assert_eq!(to.get("b").unwrap().as_deref(), Some("2"));
+        assert_eq!(from.get("a").unwrap(), None);
+    }
+}
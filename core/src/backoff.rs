@@ -0,0 +1,55 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// Exponential backoff with equal jitter
+///
+/// Each delay is drawn from the upper half of `base * 2^attempt`, capped at
+/// `max`, so concurrent clients spread out without ever retrying instantly.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            attempt: 0,
+        }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = ceiling / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_grow_within_bounds() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+
+        for ceiling in [1, 2, 4, 8, 10, 10] {
+            let ceiling = Duration::from_secs(ceiling);
+            let delay = backoff.next_delay();
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{delay:?}");
+        }
+        assert_eq!(backoff.attempt(), 6);
+    }
+}
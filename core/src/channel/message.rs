@@ -0,0 +1,169 @@
+use serde_json::Value;
+use std::fmt;
+use std::ops::Index;
+
+pub const PHX_JOIN: &str = "phx_join";
+pub const PHX_LEAVE: &str = "phx_leave";
+pub const PHX_REPLY: &str = "phx_reply";
+pub const PHX_ERROR: &str = "phx_error";
+pub const PHX_CLOSE: &str = "phx_close";
+pub const HEARTBEAT: &str = "heartbeat";
+pub const PHOENIX_TOPIC: &str = "phoenix";
+
+/// A single Phoenix channel message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub join_ref: Option<String>,
+    pub msg_ref: Option<String>,
+    pub topic: String,
+    pub event: String,
+    pub payload: Payload,
+}
+
+/// A message payload; binary payloads are only supported by serializer v2
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Json(Value),
+    Binary(Vec<u8>),
+}
+
+/// What a joined channel delivers to its subscriber
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelEvent {
+    /// A message broadcast on the topic
+    Message(Message),
+    /// The socket dropped and the topic was rejoined; messages broadcast
+    /// while disconnected may have been missed
+    Reconnected,
+}
+
+/// The payload of a `phx_reply` message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub status: String,
+    pub response: Payload,
+}
+
+impl Message {
+    pub fn new<T, E, P>(topic: T, event: E, payload: P) -> Self
+    where
+        T: Into<String>,
+        E: Into<String>,
+        P: Into<Payload>,
+    {
+        Self {
+            join_ref: None,
+            msg_ref: None,
+            topic: topic.into(),
+            event: event.into(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn is_reply(&self) -> bool {
+        self.event == PHX_REPLY
+    }
+
+    /// Decodes the payload of a JSON `phx_reply` message
+    pub fn reply(&self) -> Option<Reply> {
+        if !self.is_reply() {
+            return None;
+        }
+
+        let payload = self.payload.as_json()?;
+        Some(Reply {
+            status: payload.get("status")?.as_str()?.to_string(),
+            response: payload.get("response").cloned().unwrap_or_default().into(),
+        })
+    }
+}
+
+impl Payload {
+    pub fn as_json(&self) -> Option<&Value> {
+        match self {
+            Payload::Json(value) => Some(value),
+            Payload::Binary(_) => None,
+        }
+    }
+
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Json(_) => None,
+            Payload::Binary(bytes) => Some(bytes),
+        }
+    }
+
+    /// The JSON value of the payload, or `null` for binary payloads
+    pub fn into_json(self) -> Value {
+        match self {
+            Payload::Json(value) => value,
+            Payload::Binary(_) => Value::Null,
+        }
+    }
+}
+
+impl From<Value> for Payload {
+    fn from(value: Value) -> Self {
+        Payload::Json(value)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Payload::Binary(bytes)
+    }
+}
+
+impl Index<&str> for Payload {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        static NULL: Value = Value::Null;
+        self.as_json().map_or(&NULL, |value| &value[key])
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Json(value) => write!(f, "{value}"),
+            Payload::Binary(bytes) => write!(f, "<{} bytes>", bytes.len()),
+        }
+    }
+}
+
+impl Reply {
+    pub fn is_ok(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reply_decoding() {
+        let message = Message::new(
+            "logs:app",
+            PHX_REPLY,
+            json!({"status": "error", "response": {"reason": "unauthorized"}}),
+        );
+
+        let reply = message.reply().unwrap();
+        assert!(!reply.is_ok());
+        assert_eq!(reply.response["reason"], "unauthorized");
+
+        let broadcast = Message::new("logs:app", "line", json!({}));
+        assert!(broadcast.reply().is_none());
+    }
+
+    #[test]
+    fn test_binary_payload() {
+        let payload = Payload::from(vec![1, 2, 3]);
+        assert_eq!(payload.as_binary(), Some(&[1, 2, 3][..]));
+        assert_eq!(payload["anything"], Value::Null);
+        assert_eq!(payload.to_string(), "<3 bytes>");
+    }
+}
@@ -0,0 +1,12 @@
+pub(crate) mod message;
+mod serializer;
+mod socket;
+mod subscription;
+mod topic;
+mod transport;
+
+pub use message::{ChannelEvent, Message, Payload, Reply};
+pub use serializer::Serializer;
+pub use socket::Socket;
+pub use subscription::{Subscription, TopicEvent};
+pub use topic::Channel;
@@ -0,0 +1,369 @@
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message as Frame;
+
+use super::message::{Message, Payload, Reply};
+use crate::{Result, RigError};
+
+const KIND_PUSH: u8 = 0;
+const KIND_REPLY: u8 = 1;
+const KIND_BROADCAST: u8 = 2;
+
+/// Phoenix socket wire formats, selected by the `vsn` connect param
+///
+/// V1 sends each message as a JSON object. V2 sends a JSON array
+/// `[join_ref, ref, topic, event, payload]` and adds binary frames for
+/// binary payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serializer {
+    V1,
+    V2,
+}
+
+/// A decoded frame, with `phx_reply` envelopes already unwrapped
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Incoming {
+    Message(Message),
+    Reply {
+        topic: String,
+        msg_ref: Option<String>,
+        reply: Reply,
+    },
+}
+
+impl From<Message> for Incoming {
+    fn from(message: Message) -> Self {
+        match message.reply() {
+            Some(reply) => Incoming::Reply {
+                topic: message.topic,
+                msg_ref: message.msg_ref,
+                reply,
+            },
+            None => Incoming::Message(message),
+        }
+    }
+}
+
+impl Serializer {
+    pub fn from_vsn(vsn: &str) -> Result<Self> {
+        match vsn.split('.').next() {
+            Some("1") => Ok(Serializer::V1),
+            Some("2") => Ok(Serializer::V2),
+            _ => Err(RigError::channel(format!(
+                "Unsupported serializer version '{vsn}'"
+            ))),
+        }
+    }
+
+    pub fn vsn(self) -> &'static str {
+        match self {
+            Serializer::V1 => "1.0.0",
+            Serializer::V2 => "2.0.0",
+        }
+    }
+
+    pub fn encode(self, message: &Message) -> Result<Frame> {
+        match (&message.payload, self) {
+            (Payload::Json(payload), Serializer::V1) => {
+                let text = json!({
+                    "join_ref": message.join_ref,
+                    "ref": message.msg_ref,
+                    "topic": message.topic,
+                    "event": message.event,
+                    "payload": payload,
+                });
+                Ok(Frame::Text(text.to_string()))
+            }
+            (Payload::Json(payload), Serializer::V2) => {
+                let text = json!([
+                    message.join_ref,
+                    message.msg_ref,
+                    message.topic,
+                    message.event,
+                    payload
+                ]);
+                Ok(Frame::Text(text.to_string()))
+            }
+            (Payload::Binary(_), Serializer::V1) => {
+                Err(RigError::channel("Binary payloads require serializer v2"))
+            }
+            (Payload::Binary(payload), Serializer::V2) => encode_binary_push(message, payload),
+        }
+    }
+
+    /// Decodes a data frame; control frames decode to `None`
+    pub(crate) fn decode(self, frame: Frame) -> Result<Option<Incoming>> {
+        match frame {
+            Frame::Text(text) => {
+                let value: Value = serde_json::from_str(&text)?;
+                let message = match self {
+                    Serializer::V1 => decode_object(value)?,
+                    Serializer::V2 => decode_array(value)?,
+                };
+                Ok(Some(message.into()))
+            }
+            Frame::Binary(bytes) => match self {
+                Serializer::V1 => Err(RigError::channel("Received a binary frame on a v1 socket")),
+                Serializer::V2 => decode_binary(&bytes).map(Some),
+            },
+            Frame::Ping(_) | Frame::Pong(_) | Frame::Close(_) | Frame::Frame(_) => Ok(None),
+        }
+    }
+}
+
+fn decode_object(mut value: Value) -> Result<Message> {
+    let field = |value: &mut Value, key: &str| optional_string(value.get_mut(key).map(Value::take));
+
+    Ok(Message {
+        join_ref: field(&mut value, "join_ref")?,
+        msg_ref: field(&mut value, "ref")?,
+        topic: field(&mut value, "topic")?.ok_or_else(|| malformed("missing topic"))?,
+        event: field(&mut value, "event")?.ok_or_else(|| malformed("missing event"))?,
+        payload: value
+            .get_mut("payload")
+            .map(Value::take)
+            .unwrap_or_default()
+            .into(),
+    })
+}
+
+fn decode_array(value: Value) -> Result<Message> {
+    let Value::Array(items) = value else {
+        return Err(malformed("expected an array"));
+    };
+    let Ok([join_ref, msg_ref, topic, event, payload]) = <[Value; 5]>::try_from(items) else {
+        return Err(malformed("expected five elements"));
+    };
+
+    Ok(Message {
+        join_ref: optional_string(Some(join_ref))?,
+        msg_ref: optional_string(Some(msg_ref))?,
+        topic: optional_string(Some(topic))?.ok_or_else(|| malformed("missing topic"))?,
+        event: optional_string(Some(event))?.ok_or_else(|| malformed("missing event"))?,
+        payload: payload.into(),
+    })
+}
+
+fn optional_string(value: Option<Value>) -> Result<Option<String>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        // Refs are strings in phoenix.js but some clients send integers
+        Some(Value::Number(n)) => Ok(Some(n.to_string())),
+        Some(other) => Err(malformed(&format!("unexpected value {other}"))),
+    }
+}
+
+fn encode_binary_push(message: &Message, payload: &[u8]) -> Result<Frame> {
+    let join_ref = message.join_ref.as_deref().unwrap_or_default();
+    let msg_ref = message.msg_ref.as_deref().unwrap_or_default();
+    let fields = [join_ref, msg_ref, &message.topic, &message.event];
+
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(KIND_PUSH);
+    for field in fields {
+        let len = u8::try_from(field.len())
+            .map_err(|_| RigError::channel(format!("'{field}' is too long for a binary frame")))?;
+        frame.push(len);
+    }
+    for field in fields {
+        frame.extend_from_slice(field.as_bytes());
+    }
+    frame.extend_from_slice(payload);
+
+    Ok(Frame::Binary(frame))
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<Incoming> {
+    let (&kind, rest) = bytes
+        .split_first()
+        .ok_or_else(|| malformed("empty frame"))?;
+
+    match kind {
+        KIND_PUSH => {
+            let mut reader = BinaryReader::new(rest, 3)?;
+            Ok(Incoming::Message(Message {
+                join_ref: non_empty(reader.string()?),
+                msg_ref: None,
+                topic: reader.string()?,
+                event: reader.string()?,
+                payload: Payload::Binary(reader.rest()),
+            }))
+        }
+        KIND_REPLY => {
+            let mut reader = BinaryReader::new(rest, 4)?;
+            let _join_ref = reader.string()?;
+            let msg_ref = non_empty(reader.string()?);
+            let topic = reader.string()?;
+            let status = reader.string()?;
+            Ok(Incoming::Reply {
+                topic,
+                msg_ref,
+                reply: Reply {
+                    status,
+                    response: Payload::Binary(reader.rest()),
+                },
+            })
+        }
+        KIND_BROADCAST => {
+            let mut reader = BinaryReader::new(rest, 2)?;
+            Ok(Incoming::Message(Message {
+                join_ref: None,
+                msg_ref: None,
+                topic: reader.string()?,
+                event: reader.string()?,
+                payload: Payload::Binary(reader.rest()),
+            }))
+        }
+        other => Err(malformed(&format!("unknown binary message kind {other}"))),
+    }
+}
+
+fn non_empty(s: String) -> Option<String> {
+    (!s.is_empty()).then_some(s)
+}
+
+fn malformed(reason: &str) -> RigError {
+    RigError::channel(format!("Malformed socket message: {reason}"))
+}
+
+/// Reads the length-prefixed strings of a binary frame
+struct BinaryReader<'a> {
+    lengths: std::vec::IntoIter<usize>,
+    data: &'a [u8],
+}
+
+impl<'a> BinaryReader<'a> {
+    fn new(bytes: &'a [u8], fields: usize) -> Result<Self> {
+        if bytes.len() < fields {
+            return Err(malformed("truncated header"));
+        }
+        let (header, data) = bytes.split_at(fields);
+        let lengths: Vec<usize> = header.iter().map(|&len| len as usize).collect();
+        Ok(Self {
+            lengths: lengths.into_iter(),
+            data,
+        })
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.lengths.next().unwrap_or_default();
+        if self.data.len() < len {
+            return Err(malformed("truncated frame"));
+        }
+        let (field, data) = self.data.split_at(len);
+        self.data = data;
+        String::from_utf8(field.to_vec()).map_err(|_| malformed("invalid UTF-8"))
+    }
+
+    fn rest(self) -> Vec<u8> {
+        self.data.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::message::PHX_JOIN;
+
+    fn join() -> Message {
+        let mut message = Message::new("logs:app", PHX_JOIN, json!({"tail": 100}));
+        message.join_ref = Some("1".to_string());
+        message.msg_ref = Some("1".to_string());
+        message
+    }
+
+    fn text(frame: &Frame) -> Value {
+        match frame {
+            Frame::Text(text) => serde_json::from_str(text).unwrap(),
+            other => panic!("expected a text frame, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_v1_round_trip() {
+        let frame = Serializer::V1.encode(&join()).unwrap();
+        assert_eq!(
+            text(&frame),
+            json!({
+                "join_ref": "1",
+                "ref": "1",
+                "topic": "logs:app",
+                "event": "phx_join",
+                "payload": {"tail": 100}
+            })
+        );
+        assert_eq!(
+            Serializer::V1.decode(frame).unwrap(),
+            Some(Incoming::Message(join()))
+        );
+    }
+
+    #[test]
+    fn test_v2_round_trip() {
+        let frame = Serializer::V2.encode(&join()).unwrap();
+        assert_eq!(
+            text(&frame),
+            json!(["1", "1", "logs:app", "phx_join", {"tail": 100}])
+        );
+        assert_eq!(
+            Serializer::V2.decode(frame).unwrap(),
+            Some(Incoming::Message(join()))
+        );
+    }
+
+    #[test]
+    fn test_binary_push_encoding() {
+        let mut message = Message::new("shell:web", "input", vec![0xde, 0xad]);
+        message.join_ref = Some("1".to_string());
+        message.msg_ref = Some("12".to_string());
+
+        let Frame::Binary(bytes) = Serializer::V2.encode(&message).unwrap() else {
+            panic!("expected a binary frame");
+        };
+        let mut expected = vec![0, 1, 2, 9, 5];
+        expected.extend_from_slice(b"112shell:webinput");
+        expected.extend_from_slice(&[0xde, 0xad]);
+        assert_eq!(bytes, expected);
+
+        assert!(Serializer::V1.encode(&message).is_err());
+    }
+
+    #[test]
+    fn test_binary_decoding() {
+        let mut broadcast = vec![2, 9, 6];
+        broadcast.extend_from_slice(b"shell:weboutput");
+        broadcast.extend_from_slice(b"ls\n");
+        let Some(Incoming::Message(message)) =
+            Serializer::V2.decode(Frame::Binary(broadcast)).unwrap()
+        else {
+            panic!("expected a message");
+        };
+        assert_eq!(message.topic, "shell:web");
+        assert_eq!(message.event, "output");
+        assert_eq!(message.payload, Payload::Binary(b"ls\n".to_vec()));
+
+        let mut reply = vec![1, 1, 2, 9, 2];
+        reply.extend_from_slice(b"112shell:webok");
+        reply.extend_from_slice(&[7]);
+        assert_eq!(
+            Serializer::V2.decode(Frame::Binary(reply)).unwrap(),
+            Some(Incoming::Reply {
+                topic: "shell:web".to_string(),
+                msg_ref: Some("12".to_string()),
+                reply: Reply {
+                    status: "ok".to_string(),
+                    response: Payload::Binary(vec![7]),
+                },
+            })
+        );
+
+        assert!(Serializer::V2.decode(Frame::Binary(vec![2, 9])).is_err());
+    }
+
+    #[test]
+    fn test_from_vsn() {
+        assert_eq!(Serializer::from_vsn("1.0.0").unwrap(), Serializer::V1);
+        assert_eq!(Serializer::from_vsn("2.0.0").unwrap(), Serializer::V2);
+        assert!(Serializer::from_vsn("3.0.0").is_err());
+    }
+}
@@ -0,0 +1,935 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use super::message::{ChannelEvent, Message, Payload, Reply, HEARTBEAT, PHOENIX_TOPIC, PHX_JOIN};
+use super::serializer::{Incoming, Serializer};
+use super::transport::Transport;
+use super::{Channel, Subscription};
+use crate::auth::TokenProvider;
+use crate::backoff::Backoff;
+use crate::config::ConnectionConfig;
+use crate::http::session_rejected;
+use crate::network::NetworkSettings;
+use crate::{trace, Result, RigError};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// A connection to the Max Phoenix socket
+///
+/// The socket is cheap to clone; all clones share one connection, and any
+/// number of topics can be joined over it, each at most once. The
+/// connection is closed once every clone has been dropped or
+/// [`Socket::disconnect`] is called.
+///
+/// A dropped connection is retried up to `retry_attempts` times with jittered
+/// exponential backoff, after which every joined topic is rejoined and its
+/// subscriber receives [`ChannelEvent::Reconnected`].
+///
+/// Unless `vsn` is pinned in the config, the socket asks for serializer v2
+/// and falls back to v1 when the server refuses the upgrade. When the
+/// WebSocket upgrade itself fails, the socket long-polls instead; binary
+/// payloads are unavailable in that mode.
+///
+/// Sockets opened with [`Socket::connect_with_tokens`] send the current
+/// token as the `token` connect param. If the server refuses it, the
+/// provider gets one chance to refresh it before connecting fails. The
+/// provider is also polled on every heartbeat, so a token that expires
+/// mid-stream is renewed before a reconnect needs it.
+#[derive(Debug, Clone)]
+pub struct Socket {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    commands: mpsc::UnboundedSender<Command>,
+    refs: Arc<AtomicU64>,
+    timeout: Duration,
+    topics: Mutex<HashSet<String>>,
+}
+
+enum Command {
+    Push {
+        message: Message,
+        reply: Option<oneshot::Sender<Result<Reply>>>,
+    },
+    Subscribe {
+        topic: String,
+        sender: mpsc::UnboundedSender<ChannelEvent>,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    Disconnect,
+}
+
+impl Socket {
+    pub async fn connect(config: &ConnectionConfig) -> Result<Self> {
+        Self::open(config, None).await
+    }
+
+    /// Connects with the token from `tokens`, refreshing it when refused
+    pub async fn connect_with_tokens(
+        config: &ConnectionConfig,
+        tokens: Arc<dyn TokenProvider>,
+    ) -> Result<Self> {
+        Self::open(config, Some(tokens)).await
+    }
+
+    async fn open(
+        config: &ConnectionConfig,
+        tokens: Option<Arc<dyn TokenProvider>>,
+    ) -> Result<Self> {
+        let url = Url::parse(&config.websocket_url)?;
+        let serializer = config
+            .vsn
+            .as_deref()
+            .map(Serializer::from_vsn)
+            .transpose()?;
+
+        let timeout = Duration::from_secs(config.timeout);
+        let network = NetworkSettings::from_config(config)?;
+        let (transport, serializer) =
+            open(&url, serializer, timeout, &network, tokens.as_deref()).await?;
+
+        let refs = Arc::new(AtomicU64::new(0));
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let driver = Driver::new(
+            url,
+            serializer,
+            transport,
+            receiver,
+            refs.clone(),
+            config,
+            network,
+            tokens,
+        );
+        tokio::spawn(driver.run());
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                commands,
+                refs,
+                timeout,
+                topics: Mutex::new(HashSet::new()),
+            }),
+        })
+    }
+
+    /// Creates a channel handle for `topic`; call [`Channel::join`] to subscribe
+    pub fn channel<T: Into<String>>(&self, topic: T, params: Value) -> Channel {
+        Channel::new(self.clone(), topic.into(), params)
+    }
+
+    /// Joins `topic` and returns its messages as a typed stream
+    ///
+    /// Dropping the stream leaves the topic on the server.
+    pub async fn subscribe<T, S>(&self, topic: S, params: Value) -> Result<Subscription<T>>
+    where
+        T: DeserializeOwned,
+        S: Into<String>,
+    {
+        let mut channel = self.channel(topic, params);
+        channel.join().await?;
+        Ok(Subscription::new(channel))
+    }
+
+    pub fn is_connected(&self) -> bool {
+        !self.inner.commands.is_closed()
+    }
+
+    pub fn disconnect(&self) {
+        let _ = self.inner.commands.send(Command::Disconnect);
+    }
+
+    pub(crate) fn make_ref(&self) -> String {
+        next_ref(&self.inner.refs)
+    }
+
+    /// Sends `message` and waits for the matching `phx_reply`
+    pub(crate) async fn request(&self, message: Message) -> Result<Reply> {
+        let (sender, receiver) = oneshot::channel();
+        let topic = message.topic.clone();
+        self.send(Command::Push {
+            message,
+            reply: Some(sender),
+        })?;
+
+        match tokio::time::timeout(self.inner.timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(RigError::channel(format!(
+                "Socket closed before '{topic}' replied"
+            ))),
+            Err(_) => Err(RigError::channel(format!(
+                "Timed out waiting for a reply on '{topic}'"
+            ))),
+        }
+    }
+
+    /// Sends `message` without waiting for a reply
+    pub(crate) fn cast(&self, message: Message) -> Result<()> {
+        self.send(Command::Push {
+            message,
+            reply: None,
+        })
+    }
+
+    pub(crate) fn register(
+        &self,
+        topic: &str,
+        sender: mpsc::UnboundedSender<ChannelEvent>,
+    ) -> Result<()> {
+        let mut topics = self.inner.topics.lock().unwrap();
+        if !topics.insert(topic.to_string()) {
+            return Err(RigError::channel(format!(
+                "Topic '{topic}' is already joined on this socket"
+            )));
+        }
+
+        let result = self.send(Command::Subscribe {
+            topic: topic.to_string(),
+            sender,
+        });
+        if result.is_err() {
+            topics.remove(topic);
+        }
+        result
+    }
+
+    pub(crate) fn unregister(&self, topic: &str) {
+        self.inner.topics.lock().unwrap().remove(topic);
+        let _ = self.inner.commands.send(Command::Unsubscribe {
+            topic: topic.to_string(),
+        });
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.inner
+            .commands
+            .send(command)
+            .map_err(|_| RigError::channel("Socket is not connected"))
+    }
+}
+
+fn next_ref(refs: &AtomicU64) -> String {
+    (refs.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+}
+
+/// Opens a transport, authenticating with the provider's token if any
+async fn open(
+    url: &Url,
+    serializer: Option<Serializer>,
+    timeout: Duration,
+    network: &NetworkSettings,
+    tokens: Option<&dyn TokenProvider>,
+) -> Result<(Transport, Serializer)> {
+    let Some(tokens) = tokens else {
+        return Transport::open(url, serializer, timeout, network).await;
+    };
+
+    let token = tokens.token().await?;
+    let result = Transport::open(
+        &token_url(url, token.as_deref()),
+        serializer,
+        timeout,
+        network,
+    )
+    .await;
+    let rejected = match (result, token) {
+        (Err(RigError::Auth(_)), Some(rejected)) => rejected,
+        (Err(RigError::Auth(_)), None) => {
+            return Err(RigError::auth(
+                "The server requires authentication; run `rig login` first",
+            ))
+        }
+        (result, _) => return result,
+    };
+
+    debug!("Server refused the socket token, refreshing it");
+    let token = tokens
+        .refresh(&rejected)
+        .await?
+        .ok_or_else(session_rejected)?;
+    Transport::open(&token_url(url, Some(&token)), serializer, timeout, network)
+        .await
+        .map_err(|e| match e {
+            RigError::Auth(_) => session_rejected(),
+            e => e,
+        })
+}
+
+fn token_url(url: &Url, token: Option<&str>) -> Url {
+    let mut url = url.clone();
+    if let Some(token) = token {
+        url.query_pairs_mut().append_pair("token", token);
+    }
+    url
+}
+
+/// Per-topic state the driver needs to route messages and rejoin
+struct TopicState {
+    sender: mpsc::UnboundedSender<ChannelEvent>,
+    join_ref: Option<String>,
+    params: Payload,
+}
+
+enum Exit {
+    Disconnected,
+    Dropped,
+}
+
+/// Owns the connection and routes frames between it and the socket handles
+struct Driver {
+    url: Url,
+    serializer: Serializer,
+    transport: Transport,
+    commands: mpsc::UnboundedReceiver<Command>,
+    refs: Arc<AtomicU64>,
+    retry_attempts: u32,
+    timeout: Duration,
+    network: NetworkSettings,
+    tokens: Option<Arc<dyn TokenProvider>>,
+    /// Pushes awaiting a reply, and when they were sent
+    pending: HashMap<String, (oneshot::Sender<Result<Reply>>, Instant)>,
+    topics: HashMap<String, TopicState>,
+    rejoins: HashMap<String, String>,
+    heartbeat_ref: Option<String>,
+}
+
+impl Driver {
+    fn new(
+        url: Url,
+        serializer: Serializer,
+        transport: Transport,
+        commands: mpsc::UnboundedReceiver<Command>,
+        refs: Arc<AtomicU64>,
+        config: &ConnectionConfig,
+        network: NetworkSettings,
+        tokens: Option<Arc<dyn TokenProvider>>,
+    ) -> Self {
+        Self {
+            url,
+            serializer,
+            transport,
+            commands,
+            refs,
+            retry_attempts: config.retry_attempts,
+            timeout: Duration::from_secs(config.timeout),
+            network,
+            tokens,
+            pending: HashMap::new(),
+            topics: HashMap::new(),
+            rejoins: HashMap::new(),
+            heartbeat_ref: None,
+        }
+    }
+
+    async fn run(mut self) {
+        loop {
+            if let Exit::Disconnected = self.serve().await {
+                self.transport.close().await;
+                break;
+            }
+
+            // Requests in flight on the old connection will never be answered
+            self.pending.clear();
+            self.rejoins.clear();
+            self.heartbeat_ref = None;
+
+            if !self.reconnect().await {
+                warn!("Giving up on socket after {} attempts", self.retry_attempts);
+                break;
+            }
+
+            if let Err(e) = self.rejoin().await {
+                warn!("Rejoining channels failed: {}", e);
+            }
+        }
+    }
+
+    async fn serve(&mut self) -> Exit {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.tick().await;
+
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(Command::Disconnect) | None => return Exit::Disconnected,
+                    Some(command) => {
+                        if let Err(e) = self.handle_command(command).await {
+                            warn!("Socket write failed: {}", e);
+                            return Exit::Dropped;
+                        }
+                    }
+                },
+                frame = self.transport.next() => match frame {
+                    Some(Ok(frame)) => self.handle_frame(frame),
+                    Some(Err(e)) => {
+                        warn!("Socket read failed: {}", e);
+                        return Exit::Dropped;
+                    }
+                    None => {
+                        debug!("Socket closed by server");
+                        return Exit::Dropped;
+                    }
+                },
+                _ = heartbeat.tick() => {
+                    self.renew_token();
+                    if let Err(e) = self.send_heartbeat().await {
+                        warn!("{}", e);
+                        return Exit::Dropped;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) -> bool {
+        let mut backoff = Backoff::new(RECONNECT_BASE_DELAY, RECONNECT_MAX_DELAY);
+
+        while backoff.attempt() < self.retry_attempts {
+            let delay = backoff.next_delay();
+            info!(
+                "Reconnecting in {:?} (attempt {}/{})",
+                delay,
+                backoff.attempt(),
+                self.retry_attempts
+            );
+            tokio::time::sleep(delay).await;
+
+            let tokens = self.tokens.as_deref();
+            let serializer = Some(self.serializer);
+            match open(&self.url, serializer, self.timeout, &self.network, tokens).await {
+                Ok((transport, _)) => {
+                    info!("Socket reconnected");
+                    self.transport = transport;
+                    return true;
+                }
+                // Retrying cannot fix credentials the server has rejected
+                Err(e @ RigError::Auth(_)) => {
+                    warn!("{}", e);
+                    return false;
+                }
+                Err(e) => warn!("Reconnect failed: {}", e),
+            }
+        }
+
+        false
+    }
+
+    async fn rejoin(&mut self) -> Result<()> {
+        let mut joins = Vec::new();
+        for (topic, state) in self.topics.iter_mut() {
+            if state.join_ref.is_none() {
+                continue;
+            }
+
+            let join_ref = next_ref(&self.refs);
+            state.join_ref = Some(join_ref.clone());
+
+            let mut message = Message::new(topic.clone(), PHX_JOIN, state.params.clone());
+            message.join_ref = Some(join_ref.clone());
+            message.msg_ref = Some(join_ref.clone());
+            joins.push(message);
+            self.rejoins.insert(join_ref, topic.clone());
+        }
+
+        for message in joins {
+            debug!("Rejoining '{}'", message.topic);
+            self.write(&message).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Push { mut message, reply } => {
+                if let Some(state) = self.topics.get_mut(&message.topic) {
+                    if message.event == PHX_JOIN {
+                        state.join_ref = message.join_ref.clone();
+                        state.params = message.payload.clone();
+                    } else if state.join_ref.is_some() {
+                        message.join_ref = state.join_ref.clone();
+                    }
+                }
+
+                // A message that cannot be encoded fails its push, not the connection
+                let frame = match self.encode(&message) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        if let Some(reply) = reply {
+                            let _ = reply.send(Err(e));
+                        }
+                        return Ok(());
+                    }
+                };
+
+                if let Some(reply) = reply {
+                    // The caller already gave up, e.g. it timed out during a reconnect
+                    if reply.is_closed() {
+                        return Ok(());
+                    }
+                    if let Some(msg_ref) = message.msg_ref.clone() {
+                        self.pending.insert(msg_ref, (reply, Instant::now()));
+                    }
+                }
+
+                trace::frame("sent", &message);
+                self.transport.send(frame).await?;
+            }
+            Command::Subscribe { topic, sender } => {
+                let state = TopicState {
+                    sender,
+                    join_ref: None,
+                    params: Payload::Json(Value::Null),
+                };
+                self.topics.insert(topic, state);
+            }
+            Command::Unsubscribe { topic } => {
+                self.topics.remove(&topic);
+            }
+            Command::Disconnect => {}
+        }
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, frame: Frame) {
+        let message = match self.serializer.decode(frame) {
+            Ok(Some(Incoming::Message(message))) => message,
+            Ok(Some(Incoming::Reply {
+                topic,
+                msg_ref,
+                reply,
+            })) => {
+                let msg_ref = msg_ref.unwrap_or_default();
+                let sent_at = self.pending.get(&msg_ref).map(|(_, sent_at)| *sent_at);
+                trace::reply(
+                    &topic,
+                    Some(&msg_ref),
+                    &reply,
+                    sent_at.map(|sent_at| sent_at.elapsed()),
+                );
+                self.handle_reply(&topic, &msg_ref, reply);
+                return;
+            }
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Ignoring malformed socket message: {}", e);
+                return;
+            }
+        };
+
+        trace::frame("received", &message);
+        let Some(state) = self.topics.get(&message.topic) else {
+            return;
+        };
+
+        // Messages from a previous join of the same topic are stale
+        if message.join_ref.is_some() && message.join_ref != state.join_ref {
+            debug!("Dropping stale message for '{}'", message.topic);
+            return;
+        }
+
+        if state.sender.send(ChannelEvent::Message(message)).is_err() {
+            debug!("Dropping message for closed subscriber");
+        }
+    }
+
+    fn handle_reply(&mut self, topic: &str, msg_ref: &str, reply: Reply) {
+        if topic == PHOENIX_TOPIC {
+            if self.heartbeat_ref.as_deref() == Some(msg_ref) {
+                self.heartbeat_ref = None;
+            }
+        } else if let Some((sender, _)) = self.pending.remove(msg_ref) {
+            let _ = sender.send(Ok(reply));
+        } else if let Some(topic) = self.rejoins.remove(msg_ref) {
+            self.handle_rejoin(&topic, reply);
+        }
+    }
+
+    fn handle_rejoin(&mut self, topic: &str, reply: Reply) {
+        if reply.is_ok() {
+            if let Some(state) = self.topics.get(topic) {
+                let _ = state.sender.send(ChannelEvent::Reconnected);
+            }
+        } else {
+            warn!("Rejoin of '{}' was rejected: {}", topic, reply.response);
+            self.topics.remove(topic);
+        }
+    }
+
+    /// Lets the provider renew its token in the background
+    fn renew_token(&self) {
+        let Some(tokens) = self.tokens.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = tokens.token().await {
+                warn!("Token renewal failed: {}", e);
+            }
+        });
+    }
+
+    async fn send_heartbeat(&mut self) -> Result<()> {
+        if self.heartbeat_ref.is_some() {
+            return Err(RigError::channel("Heartbeat timed out"));
+        }
+
+        // Replies nobody is waiting on any more would otherwise pile up
+        self.pending.retain(|_, (sender, _)| !sender.is_closed());
+
+        let msg_ref = next_ref(&self.refs);
+        let mut message = Message::new(PHOENIX_TOPIC, HEARTBEAT, json!({}));
+        message.msg_ref = Some(msg_ref.clone());
+        self.heartbeat_ref = Some(msg_ref);
+        self.write(&message).await
+    }
+
+    fn encode(&self, message: &Message) -> Result<Frame> {
+        let frame = self.serializer.encode(message)?;
+        if matches!(frame, Frame::Binary(_)) && !self.transport.supports_binary() {
+            return Err(RigError::channel(
+                "Binary payloads are not supported over long-polling",
+            ));
+        }
+        Ok(frame)
+    }
+
+    async fn write(&mut self, message: &Message) -> Result<()> {
+        let frame = self.encode(message)?;
+        trace::frame("sent", message);
+        self.transport.send(frame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::TopicEvent;
+    use crate::Config;
+    use futures_util::{SinkExt, StreamExt};
+    use serde::Deserialize;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tokio_tungstenite::tungstenite::http::StatusCode;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Line {
+        text: String,
+    }
+
+    /// Hands out a stale token first and a fresh one on refresh
+    struct Tokens {
+        refreshed: Option<&'static str>,
+    }
+
+    #[async_trait::async_trait]
+    impl TokenProvider for Tokens {
+        async fn token(&self) -> Result<Option<String>> {
+            Ok(Some("stale".to_string()))
+        }
+
+        async fn refresh(&self, rejected: &str) -> Result<Option<String>> {
+            assert_eq!(rejected, "stale");
+            Ok(self.refreshed.map(str::to_string))
+        }
+    }
+
+    /// Acts as a minimal Phoenix endpoint for `connections` connections,
+    /// dropping every connection but the last right after the first join
+    async fn fake_server(connections: usize) -> String {
+        fake_server_with(connections, &["1.0.0", "2.0.0"], None).await
+    }
+
+    async fn fake_server_with(
+        connections: usize,
+        supported: &'static [&'static str],
+        token: Option<&'static str>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut remaining = connections;
+            while remaining > 0 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut vsn = String::new();
+                // The callback signature is fixed by tungstenite
+                #[allow(clippy::result_large_err)]
+                let negotiate = |request: &Request, response: Response| {
+                    let url = Url::parse(&format!("ws://localhost{}", request.uri())).unwrap();
+                    vsn = url
+                        .query_pairs()
+                        .find(|(key, _)| key == "vsn")
+                        .map(|(_, value)| value.into_owned())
+                        .unwrap_or_else(|| "1.0.0".to_string());
+
+                    let authorized = token.map_or(true, |token| {
+                        url.query_pairs()
+                            .any(|(key, value)| key == "token" && value == token)
+                    });
+
+                    if supported.contains(&vsn.as_str()) && authorized {
+                        Ok(response)
+                    } else {
+                        let mut refused = ErrorResponse::new(None);
+                        *refused.status_mut() = StatusCode::FORBIDDEN;
+                        Err(refused)
+                    }
+                };
+                let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, negotiate).await
+                else {
+                    continue;
+                };
+                let serializer = Serializer::from_vsn(&vsn).unwrap();
+                remaining -= 1;
+
+                while let Some(Ok(frame)) = ws.next().await {
+                    let message = match frame {
+                        Frame::Binary(bytes) => decode_client_push(&bytes),
+                        frame => match serializer.decode(frame) {
+                            Ok(Some(Incoming::Message(message))) => message,
+                            _ => continue,
+                        },
+                    };
+                    let response = match message.event.as_str() {
+                        "phx_join" if message.topic == "room:denied" => {
+                            json!({"status": "error", "response": {"reason": "unauthorized"}})
+                        }
+                        "ping" => json!({"status": "ok", "response": {"pong": true}}),
+                        _ => json!({"status": "ok", "response": {}}),
+                    };
+
+                    let mut reply = Message::new(message.topic.clone(), "phx_reply", response);
+                    reply.join_ref = message.join_ref.clone();
+                    reply.msg_ref = message.msg_ref.clone();
+                    ws.send(serializer.encode(&reply).unwrap()).await.unwrap();
+
+                    if let Payload::Binary(input) = &message.payload {
+                        let mut output = vec![2, message.topic.len() as u8, 6];
+                        output.extend_from_slice(message.topic.as_bytes());
+                        output.extend_from_slice(b"output");
+                        output.extend_from_slice(input);
+                        ws.send(Frame::Binary(output)).await.unwrap();
+                    }
+
+                    if message.event == "phx_join" {
+                        let mut broadcast =
+                            Message::new(message.topic.clone(), "line", json!({"text": "hello"}));
+                        broadcast.join_ref = message.join_ref;
+                        ws.send(serializer.encode(&broadcast).unwrap())
+                            .await
+                            .unwrap();
+
+                        if remaining > 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        format!("ws://{addr}/socket/websocket")
+    }
+
+    /// Client pushes carry a ref, unlike the server pushes the client decodes
+    fn decode_client_push(bytes: &[u8]) -> Message {
+        let lengths: Vec<usize> = bytes[1..5].iter().map(|&len| len as usize).collect();
+        let mut fields = Vec::new();
+        let mut offset = 5;
+        for len in lengths {
+            fields.push(String::from_utf8(bytes[offset..offset + len].to_vec()).unwrap());
+            offset += len;
+        }
+
+        let mut message = Message::new(
+            fields[2].clone(),
+            fields[3].clone(),
+            bytes[offset..].to_vec(),
+        );
+        message.join_ref = Some(fields[0].clone());
+        message.msg_ref = Some(fields[1].clone());
+        message
+    }
+
+    fn expect_line(event: Option<ChannelEvent>) {
+        match event {
+            Some(ChannelEvent::Message(message)) => {
+                assert_eq!(message.event, "line");
+                assert_eq!(message.payload["text"], "hello");
+            }
+            other => panic!("expected a line, got {other:?}"),
+        }
+    }
+
+    fn config(websocket_url: String) -> ConnectionConfig {
+        ConnectionConfig {
+            websocket_url,
+            timeout: 5,
+            ..Config::default().connection
+        }
+    }
+
+    #[tokio::test]
+    async fn test_join_push_and_receive() {
+        let socket = Socket::connect(&config(fake_server(1).await))
+            .await
+            .unwrap();
+
+        let mut channel = socket.channel("room:lobby", json!({}));
+        channel.join().await.unwrap();
+        assert!(channel.is_joined());
+
+        expect_line(channel.recv().await);
+
+        let reply = channel.push("ping", json!({})).await.unwrap();
+        assert!(reply.is_ok());
+        assert_eq!(reply.response["pong"], true);
+
+        channel.leave().await.unwrap();
+        assert!(!channel.is_joined());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_join() {
+        let socket = Socket::connect(&config(fake_server(1).await))
+            .await
+            .unwrap();
+
+        let mut channel = socket.channel("room:denied", json!({}));
+        let err = channel.join().await.unwrap_err();
+        assert!(err.to_string().contains("unauthorized"));
+        assert!(!channel.is_joined());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_rejoins_topics() {
+        let socket = Socket::connect(&config(fake_server(2).await))
+            .await
+            .unwrap();
+
+        let mut channel = socket.channel("room:lobby", json!({"tail": 10}));
+        channel.join().await.unwrap();
+        expect_line(channel.recv().await);
+
+        assert_eq!(channel.recv().await, Some(ChannelEvent::Reconnected));
+        expect_line(channel.recv().await);
+
+        let reply = channel.push("ping", json!({})).await.unwrap();
+        assert_eq!(reply.response["pong"], true);
+    }
+
+    #[tokio::test]
+    async fn test_typed_subscriptions_share_socket() {
+        let socket = Socket::connect(&config(fake_server(1).await))
+            .await
+            .unwrap();
+
+        let mut api = socket
+            .subscribe::<Line, _>("logs:api", json!({}))
+            .await
+            .unwrap();
+        let mut web = socket
+            .subscribe::<Line, _>("logs:web", json!({}))
+            .await
+            .unwrap();
+
+        let duplicate = socket.subscribe::<Line, _>("logs:api", json!({})).await;
+        assert!(duplicate.is_err());
+
+        for subscription in [&mut api, &mut web] {
+            let event = subscription.next().await.unwrap().unwrap();
+            assert_eq!(
+                event,
+                TopicEvent::Message {
+                    event: "line".to_string(),
+                    payload: Line {
+                        text: "hello".to_string()
+                    },
+                }
+            );
+        }
+
+        // Dropping leaves the topic, so it can be joined again
+        drop(api);
+        let mut api = socket
+            .subscribe::<Line, _>("logs:api", json!({}))
+            .await
+            .unwrap();
+        assert!(api.next().await.unwrap().is_ok());
+
+        web.unsubscribe().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_binary_push_and_broadcast() {
+        let socket = Socket::connect(&config(fake_server(1).await))
+            .await
+            .unwrap();
+
+        let mut channel = socket.channel("shell:web", json!({}));
+        channel.join().await.unwrap();
+        expect_line(channel.recv().await);
+
+        let reply = channel.push("input", b"ls\n".to_vec()).await.unwrap();
+        assert!(reply.is_ok());
+
+        match channel.recv().await {
+            Some(ChannelEvent::Message(message)) => {
+                assert_eq!(message.event, "output");
+                assert_eq!(message.payload, Payload::Binary(b"ls\n".to_vec()));
+            }
+            other => panic!("expected output, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_v1_serializer() {
+        let url = fake_server_with(1, &["1.0.0"], None).await;
+        let socket = Socket::connect(&config(url)).await.unwrap();
+
+        let mut channel = socket.channel("room:lobby", json!({}));
+        channel.join().await.unwrap();
+        expect_line(channel.recv().await);
+
+        // Binary frames cannot be expressed in v1
+        assert!(channel.push("input", vec![1]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_rejected_token() {
+        let url = fake_server_with(1, &["2.0.0"], Some("fresh")).await;
+        let tokens = Arc::new(Tokens {
+            refreshed: Some("fresh"),
+        });
+        let socket = Socket::connect_with_tokens(&config(url), tokens)
+            .await
+            .unwrap();
+
+        let mut channel = socket.channel("room:lobby", json!({}));
+        channel.join().await.unwrap();
+        expect_line(channel.recv().await);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_token_is_an_auth_error() {
+        let url = fake_server_with(1, &["2.0.0"], Some("fresh")).await;
+        let tokens = Arc::new(Tokens { refreshed: None });
+
+        let err = Socket::connect_with_tokens(&config(url), tokens)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RigError::Auth(_)), "{err:?}");
+        assert!(err.to_string().contains("rig login"));
+    }
+}
@@ -0,0 +1,89 @@
+use futures_util::Stream;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::message::{ChannelEvent, Payload, PHX_ERROR};
+use super::Channel;
+use crate::{Result, RigError};
+
+/// An event from a [`Subscription`] with its payload decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicEvent<T> {
+    Message {
+        event: String,
+        payload: T,
+    },
+    /// See [`ChannelEvent::Reconnected`]
+    Reconnected,
+}
+
+/// A joined topic whose messages are decoded into `T`
+///
+/// Payloads that do not decode into `T` are yielded as errors rather than
+/// ending the stream. Dropping the subscription leaves the topic.
+#[derive(Debug)]
+pub struct Subscription<T> {
+    channel: Channel,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    pub(crate) fn new(channel: Channel) -> Self {
+        Self {
+            channel,
+            _payload: PhantomData,
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        self.channel.topic()
+    }
+
+    /// The underlying channel, e.g. to push events to the topic
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// Leaves the topic and waits for the server to acknowledge it
+    pub async fn unsubscribe(mut self) -> Result<()> {
+        self.channel.leave().await
+    }
+}
+
+impl<T: DeserializeOwned> Stream for Subscription<T> {
+    type Item = Result<TopicEvent<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let message = match Pin::new(&mut self.channel).poll_next(cx) {
+            Poll::Ready(Some(ChannelEvent::Message(message))) => message,
+            Poll::Ready(Some(ChannelEvent::Reconnected)) => {
+                return Poll::Ready(Some(Ok(TopicEvent::Reconnected)))
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        if message.event == PHX_ERROR {
+            return Poll::Ready(Some(Err(RigError::channel(format!(
+                "Channel '{}' crashed on the server",
+                message.topic
+            )))));
+        }
+
+        let item = match message.payload {
+            Payload::Json(payload) => serde_json::from_value(payload)
+                .map(|payload| TopicEvent::Message {
+                    event: message.event,
+                    payload,
+                })
+                .map_err(RigError::from),
+            Payload::Binary(_) => Err(RigError::channel(format!(
+                "Unexpected binary '{}' message on '{}'",
+                message.event, message.topic
+            ))),
+        };
+        Poll::Ready(Some(item))
+    }
+}
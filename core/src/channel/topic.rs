@@ -0,0 +1,179 @@
+use futures_util::Stream;
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::message::{
+    ChannelEvent, Message, Payload, Reply, PHX_CLOSE, PHX_ERROR, PHX_JOIN, PHX_LEAVE,
+};
+use super::Socket;
+use crate::{Result, RigError};
+
+/// A single topic on a [`Socket`]
+///
+/// Once joined, the channel is a [`Stream`] of the topic's events. Dropping a
+/// joined channel leaves the topic on the server.
+#[derive(Debug)]
+pub struct Channel {
+    socket: Socket,
+    topic: String,
+    params: Value,
+    join_ref: Option<String>,
+    messages: Option<mpsc::UnboundedReceiver<ChannelEvent>>,
+}
+
+impl Channel {
+    pub(crate) fn new(socket: Socket, topic: String, params: Value) -> Self {
+        Self {
+            socket,
+            topic,
+            params,
+            join_ref: None,
+            messages: None,
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn is_joined(&self) -> bool {
+        self.join_ref.is_some()
+    }
+
+    /// Joins the topic, returning the server's join response
+    pub async fn join(&mut self) -> Result<Value> {
+        if self.is_joined() {
+            return Err(RigError::channel(format!(
+                "Already joined '{}'",
+                self.topic
+            )));
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.socket.register(&self.topic, sender)?;
+
+        let join_ref = self.socket.make_ref();
+        let mut message = Message::new(self.topic.clone(), PHX_JOIN, self.params.clone());
+        message.join_ref = Some(join_ref.clone());
+        message.msg_ref = Some(join_ref.clone());
+
+        let reply = match self.socket.request(message).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                self.socket.unregister(&self.topic);
+                return Err(e);
+            }
+        };
+
+        if !reply.is_ok() {
+            self.socket.unregister(&self.topic);
+            return Err(RigError::channel(format!(
+                "Join of '{}' was rejected: {}",
+                self.topic, reply.response
+            )));
+        }
+
+        self.join_ref = Some(join_ref);
+        self.messages = Some(receiver);
+        Ok(reply.response.into_json())
+    }
+
+    /// Leaves the topic; further messages for it are discarded
+    pub async fn leave(&mut self) -> Result<()> {
+        if !self.is_joined() {
+            return Ok(());
+        }
+
+        let result = self
+            .push(PHX_LEAVE, Value::Object(Default::default()))
+            .await;
+        self.closed();
+        result.map(|_| ())
+    }
+
+    /// Pushes an event to the topic and waits for the server's reply
+    ///
+    /// Binary payloads (`Vec<u8>`) require serializer v2.
+    pub async fn push<E, P>(&self, event: E, payload: P) -> Result<Reply>
+    where
+        E: Into<String>,
+        P: Into<Payload>,
+    {
+        let join_ref = self
+            .join_ref
+            .clone()
+            .ok_or_else(|| RigError::channel(format!("Not joined to '{}'", self.topic)))?;
+
+        let mut message = Message::new(self.topic.clone(), event, payload);
+        message.join_ref = Some(join_ref);
+        message.msg_ref = Some(self.socket.make_ref());
+        self.socket.request(message).await
+    }
+
+    /// Waits for the next event on the topic
+    ///
+    /// Returns `None` when the channel is not joined, the server closed the
+    /// topic, or the socket closed for good.
+    pub async fn recv(&mut self) -> Option<ChannelEvent> {
+        futures_util::StreamExt::next(self).await
+    }
+
+    fn closed(&mut self) {
+        self.socket.unregister(&self.topic);
+        self.join_ref = None;
+        self.messages = None;
+    }
+}
+
+impl Stream for Channel {
+    type Item = ChannelEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(messages) = self.messages.as_mut() else {
+            return Poll::Ready(None);
+        };
+
+        let message = match messages.poll_recv(cx) {
+            Poll::Ready(Some(ChannelEvent::Message(message))) => message,
+            Poll::Ready(Some(ChannelEvent::Reconnected)) => {
+                return Poll::Ready(Some(ChannelEvent::Reconnected))
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match message.event.as_str() {
+            PHX_CLOSE => {
+                self.closed();
+                Poll::Ready(None)
+            }
+            PHX_ERROR => {
+                warn!("Channel '{}' crashed on the server", self.topic);
+                Poll::Ready(Some(ChannelEvent::Message(message)))
+            }
+            _ => Poll::Ready(Some(ChannelEvent::Message(message))),
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let Some(join_ref) = self.join_ref.take() else {
+            return;
+        };
+
+        debug!("Leaving '{}'", self.topic);
+        let mut message = Message::new(
+            self.topic.clone(),
+            PHX_LEAVE,
+            Value::Object(Default::default()),
+        );
+        message.join_ref = Some(join_ref);
+        message.msg_ref = Some(self.socket.make_ref());
+        let _ = self.socket.cast(message);
+        self.socket.unregister(&self.topic);
+    }
+}
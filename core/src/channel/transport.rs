@@ -0,0 +1,456 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as Frame};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+use url::Url;
+
+use super::serializer::Serializer;
+use crate::network::NetworkSettings;
+use crate::{trace, HttpClient, Result, RigError};
+
+type Connection = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The connection underneath a socket
+///
+/// WebSocket is preferred; Phoenix's long-poll transport is used when the
+/// upgrade is blocked, e.g. by a proxy that strips `Upgrade` headers.
+pub(crate) enum Transport {
+    WebSocket(Box<Connection>),
+    LongPoll(Box<LongPoll>),
+}
+
+impl Transport {
+    /// Opens a transport, trying each candidate serializer unless one is pinned
+    pub(crate) async fn open(
+        url: &Url,
+        serializer: Option<Serializer>,
+        timeout: Duration,
+        network: &NetworkSettings,
+    ) -> Result<(Self, Serializer)> {
+        let candidates = match serializer {
+            Some(serializer) => vec![serializer],
+            None => vec![Serializer::V2, Serializer::V1],
+        };
+
+        let error = match open_websocket(url, &candidates, network).await {
+            Ok((connection, serializer)) => {
+                return Ok((Transport::WebSocket(Box::new(connection)), serializer))
+            }
+            // The server answered; it just won't take us, so polling won't help
+            Err(e) if is_refusal(&e) => return Err(refused()),
+            Err(e) => e,
+        };
+
+        warn!(
+            "WebSocket upgrade failed ({}), falling back to long-polling",
+            error
+        );
+        match LongPoll::open(url, &candidates, timeout, network).await {
+            Ok((long_poll, serializer)) => {
+                Ok((Transport::LongPoll(Box::new(long_poll)), serializer))
+            }
+            Err(e) => {
+                debug!("Long-polling failed too: {}", e);
+                Err(error.into())
+            }
+        }
+    }
+
+    pub(crate) fn supports_binary(&self) -> bool {
+        matches!(self, Transport::WebSocket(_))
+    }
+
+    pub(crate) async fn send(&mut self, frame: Frame) -> Result<()> {
+        match self {
+            Transport::WebSocket(connection) => Ok(connection.send(frame).await?),
+            Transport::LongPoll(long_poll) => match frame {
+                Frame::Text(text) => long_poll.send(&text).await,
+                _ => Err(RigError::channel(
+                    "Only text frames can be sent over long-polling",
+                )),
+            },
+        }
+    }
+
+    /// The next frame from the server, or `None` once the transport closed
+    pub(crate) async fn next(&mut self) -> Option<Result<Frame>> {
+        match self {
+            Transport::WebSocket(connection) => connection
+                .next()
+                .await
+                .map(|frame| frame.map_err(RigError::from)),
+            Transport::LongPoll(long_poll) => long_poll.frames.recv().await,
+        }
+    }
+
+    pub(crate) async fn close(&mut self) {
+        match self {
+            Transport::WebSocket(connection) => {
+                let _ = connection.as_mut().close(None).await;
+            }
+            Transport::LongPoll(long_poll) => long_poll.poller.abort(),
+        }
+    }
+}
+
+async fn open_websocket(
+    url: &Url,
+    candidates: &[Serializer],
+    network: &NetworkSettings,
+) -> std::result::Result<(Connection, Serializer), WsError> {
+    let mut refusal = None;
+    for &serializer in candidates {
+        let url = connect_url(url, serializer);
+        debug!("Connecting to {}", trace::redact_url(&url));
+
+        match network.connect_websocket(&url).await {
+            Ok(connection) => return Ok((connection, serializer)),
+            Err(e) if is_refusal(&e) => {
+                debug!("Server refused serializer {}", serializer.vsn());
+                refusal = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(refusal.unwrap_or(WsError::ConnectionClosed))
+}
+
+/// Phoenix answers 403 when it does not speak the requested vsn or the
+/// socket's `connect/3` rejected the params, e.g. an invalid token
+fn is_refusal(error: &WsError) -> bool {
+    matches!(
+        error,
+        WsError::Http(response)
+            if matches!(response.status(), StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED)
+    )
+}
+
+/// Reported as an auth error so the socket knows a fresh token may help
+fn refused() -> RigError {
+    RigError::auth("The server refused the socket connection")
+}
+
+fn connect_url(url: &Url, serializer: Serializer) -> Url {
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "vsn")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(params)
+        .append_pair("vsn", serializer.vsn());
+    url
+}
+
+/// Maps `wss://host/socket/websocket` to `https://host/socket/longpoll`
+fn long_poll_url(url: &Url) -> Result<Url> {
+    let mut url = url.clone();
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        other => {
+            return Err(RigError::channel(format!(
+                "Unsupported socket scheme '{other}'"
+            )))
+        }
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| RigError::channel("Could not derive the long-poll URL"))?;
+
+    let path = url.path().trim_end_matches('/');
+    let path = path.strip_suffix("/websocket").unwrap_or(path);
+    url.set_path(&format!("{path}/longpoll"));
+    Ok(url)
+}
+
+#[derive(Debug, Deserialize)]
+struct PollResponse {
+    status: u16,
+    token: Option<String>,
+    #[serde(default)]
+    messages: Vec<String>,
+}
+
+/// A Phoenix long-poll session
+///
+/// A background task keeps one poll request in flight and forwards the
+/// messages it returns; pushes are sent as separate POST requests.
+pub(crate) struct LongPoll {
+    http: HttpClient,
+    url: Url,
+    frames: mpsc::UnboundedReceiver<Result<Frame>>,
+    poller: JoinHandle<()>,
+}
+
+impl LongPoll {
+    async fn open(
+        url: &Url,
+        candidates: &[Serializer],
+        timeout: Duration,
+        network: &NetworkSettings,
+    ) -> Result<(Self, Serializer)> {
+        let base = long_poll_url(url)?;
+        let http = HttpClient::with_network(base.as_str(), timeout, 0, network)?;
+
+        for &serializer in candidates {
+            let mut url = connect_url(&base, serializer);
+            debug!("Opening long-poll session at {}", trace::redact_url(&url));
+
+            let response = http.get(url.as_str()).await?;
+            if matches!(
+                response.status(),
+                reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::UNAUTHORIZED
+            ) {
+                debug!("Server refused serializer {}", serializer.vsn());
+                continue;
+            }
+
+            // A new session is announced as "gone" along with its token
+            let session: PollResponse = response
+                .error_for_status()
+                .map_err(trace::without_url)?
+                .json()
+                .await
+                .map_err(trace::without_url)?;
+            let token = match (session.status, session.token) {
+                (410, Some(token)) => token,
+                _ => return Err(RigError::channel("Server did not open a long-poll session")),
+            };
+            url.query_pairs_mut().append_pair("token", &token);
+
+            let (sender, frames) = mpsc::unbounded_channel();
+            let poller = tokio::spawn(poll(http.clone(), url.clone(), sender));
+            let long_poll = Self {
+                http,
+                url,
+                frames,
+                poller,
+            };
+            return Ok((long_poll, serializer));
+        }
+
+        Err(refused())
+    }
+
+    async fn send(&self, text: &str) -> Result<()> {
+        let body: Value = serde_json::from_str(text)?;
+        let response = self.http.post(self.url.as_str(), &body).await?;
+        let ack: PollResponse = response
+            .error_for_status()
+            .map_err(trace::without_url)?
+            .json()
+            .await
+            .map_err(trace::without_url)?;
+
+        match ack.status {
+            200 => Ok(()),
+            410 => Err(RigError::channel("Long-poll session expired")),
+            status => Err(RigError::channel(format!(
+                "Long-poll push failed with status {status}"
+            ))),
+        }
+    }
+}
+
+impl Drop for LongPoll {
+    fn drop(&mut self) {
+        self.poller.abort();
+    }
+}
+
+async fn poll(http: HttpClient, url: Url, frames: mpsc::UnboundedSender<Result<Frame>>) {
+    loop {
+        let response = match http.get(url.as_str()).await {
+            Ok(response) => response,
+            Err(e) => {
+                let _ = frames.send(Err(e));
+                return;
+            }
+        };
+        // The session token is in the URL, so it is kept out of errors
+        let poll: PollResponse = match response.error_for_status() {
+            Ok(response) => match response.json().await {
+                Ok(poll) => poll,
+                Err(e) => {
+                    let _ = frames.send(Err(trace::without_url(e)));
+                    return;
+                }
+            },
+            Err(e) => {
+                let _ = frames.send(Err(trace::without_url(e)));
+                return;
+            }
+        };
+
+        match poll.status {
+            200 => {
+                for message in poll.messages {
+                    if frames.send(Ok(Frame::Text(message))).is_err() {
+                        return;
+                    }
+                }
+            }
+            // The poll window elapsed without messages
+            204 => {}
+            _ => {
+                let _ = frames.send(Err(RigError::channel("Long-poll session expired")));
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelEvent, Socket};
+    use crate::Config;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    type Queue = Arc<Mutex<Vec<String>>>;
+
+    /// Hands out queued messages, or opens a session for token-less polls
+    struct PollResponder(Queue);
+
+    impl Respond for PollResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            if !request.url.query_pairs().any(|(key, _)| key == "token") {
+                return ResponseTemplate::new(200)
+                    .set_body_json(json!({"status": 410, "token": "session"}));
+            }
+
+            let messages = std::mem::take(&mut *self.0.lock().unwrap());
+            if messages.is_empty() {
+                return ResponseTemplate::new(200)
+                    .set_body_json(json!({"status": 204, "token": "session"}))
+                    .set_delay(Duration::from_millis(50));
+            }
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"status": 200, "token": "session", "messages": messages}))
+        }
+    }
+
+    /// Acknowledges every push and queues its reply for the next poll
+    struct PushResponder(Queue);
+
+    impl Respond for PushResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let push: Vec<Value> = serde_json::from_slice(&request.body).unwrap();
+            let reply =
+                json!([push[0], push[1], push[2], "phx_reply", {"status": "ok", "response": {}}]);
+
+            let mut queue = self.0.lock().unwrap();
+            queue.push(reply.to_string());
+            if push[3] == "phx_join" {
+                let line = json!([push[0], null, push[2], "line", {"text": "hello"}]);
+                queue.push(line.to_string());
+            }
+            ResponseTemplate::new(200).set_body_json(json!({"status": 200}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_long_polling() {
+        let server = MockServer::start().await;
+        let queue = Queue::default();
+        Mock::given(method("GET"))
+            .and(path("/socket/longpoll"))
+            .respond_with(PollResponder(queue.clone()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/socket/longpoll"))
+            .respond_with(PushResponder(queue))
+            .mount(&server)
+            .await;
+
+        // The mock server has no WebSocket route, so the upgrade fails
+        let config = crate::config::ConnectionConfig {
+            websocket_url: format!("ws://{}/socket/websocket", server.address()),
+            timeout: 5,
+            ..Config::default().connection
+        };
+        let socket = Socket::connect(&config).await.unwrap();
+
+        let mut channel = socket.channel("logs:web", json!({}));
+        channel.join().await.unwrap();
+        match channel.recv().await {
+            Some(ChannelEvent::Message(message)) => assert_eq!(message.payload["text"], "hello"),
+            other => panic!("expected a line, got {other:?}"),
+        }
+
+        assert!(channel.push("input", vec![1]).await.is_err());
+        assert!(channel.push("ping", json!({})).await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_errors_hide_the_session_token() {
+        let server = MockServer::start().await;
+        Mock::given(query_param("token", "s3cret-session"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"status": 410, "token": "s3cret-session"})),
+            )
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("ws://{}/socket/websocket", server.address())).unwrap();
+        let (mut long_poll, _) = LongPoll::open(
+            &url,
+            &[Serializer::V2],
+            Duration::from_secs(5),
+            &NetworkSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        let polled = long_poll.frames.recv().await.unwrap().unwrap_err();
+        let pushed = long_poll.send("[]").await.unwrap_err();
+        for error in [polled, pushed] {
+            assert!(error.to_string().contains("503"), "{error}");
+            assert!(!error.to_string().contains("s3cret-session"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_connect_url_sets_vsn() {
+        let url = Url::parse("wss://api.max.dev/socket/websocket?vsn=1.0.0&region=eu").unwrap();
+        assert_eq!(
+            connect_url(&url, Serializer::V2).as_str(),
+            "wss://api.max.dev/socket/websocket?region=eu&vsn=2.0.0"
+        );
+    }
+
+    #[test]
+    fn test_long_poll_url() {
+        let url = Url::parse("wss://api.max.dev/socket/websocket?region=eu").unwrap();
+        assert_eq!(
+            long_poll_url(&url).unwrap().as_str(),
+            "https://api.max.dev/socket/longpoll?region=eu"
+        );
+
+        let url = Url::parse("ws://localhost:4000/socket").unwrap();
+        assert_eq!(
+            long_poll_url(&url).unwrap().as_str(),
+            "http://localhost:4000/socket/longpoll"
+        );
+    }
+}
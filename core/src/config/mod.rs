@@ -2,10 +2,18 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+mod profiles;
+
+pub use profiles::{Profile, Profiles, DEFAULT_PROFILE};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub connection: ConnectionConfig,
     pub defaults: DefaultsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +22,25 @@ pub struct ConnectionConfig {
     pub websocket_url: String,
     pub timeout: u64,
     pub retry_attempts: u32,
+    #[serde(default)]
+    pub vsn: Option<String>,
+    /// HTTP proxy for API and socket traffic; `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `ALL_PROXY` apply when unset
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Comma separated hosts to reach without the proxy; `NO_PROXY` applies
+    /// when unset
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// PEM bundle of extra CA certificates to trust, e.g. an internal CA
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+    /// PKCS#8 PEM key for `client_cert`; read from `client_cert` when unset
+    #[serde(default)]
+    pub client_key: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +51,69 @@ pub struct DefaultsConfig {
     pub follow_logs: bool,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// Where credentials are stored
+    #[serde(default)]
+    pub credential_store: CredentialStoreKind,
+    /// A git-credential style command to fetch and store tokens with;
+    /// takes precedence over `credential_store`
+    #[serde(default)]
+    pub credential_helper: Option<String>,
+}
+
+/// The on-disk cache of GET responses, off unless enabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Seconds a response is served without revalidating it with the server
+    pub ttl: u64,
+    /// Megabytes the cache may take on disk
+    pub max_size_mb: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: 0,
+            max_size_mb: 50,
+        }
+    }
+}
+
+/// Credential store backends, selected by `auth.credential_store`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialStoreKind {
+    /// The OS keyring when available, otherwise the encrypted file
+    #[default]
+    Auto,
+    Keyring,
+    /// An encrypted file in the config directory
+    File,
+    /// Process memory only; nothing outlives the command
+    Memory,
+}
+
+impl std::str::FromStr for CredentialStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "keyring" => Ok(Self::Keyring),
+            "file" => Ok(Self::File),
+            "memory" => Ok(Self::Memory),
+            other => anyhow::bail!(
+                "Unknown credential store '{}'; expected auto, keyring, file or memory",
+                other
+            ),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -32,6 +122,12 @@ impl Default for Config {
                 websocket_url: "wss://api.max.dev/socket/websocket".to_string(),
                 timeout: 30,
                 retry_attempts: 3,
+                vsn: None,
+                proxy: None,
+                no_proxy: None,
+                ca_bundle: None,
+                client_cert: None,
+                client_key: None,
             },
             defaults: DefaultsConfig {
                 output_format: "table".to_string(),
@@ -39,6 +135,8 @@ impl Default for Config {
                 auto_connect: true,
                 follow_logs: false,
             },
+            auth: AuthConfig::default(),
+            cache: CacheConfig::default(),
         }
     }
 }
@@ -65,4 +163,12 @@ impl Config {
     pub fn default_config_path() -> Result<PathBuf> {
         Ok(Self::config_dir()?.join("config.toml"))
     }
+
+    pub fn profiles_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("profiles.json"))
+    }
+
+    pub fn cache_dir() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("cache"))
+    }
 }
@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::files::write_private;
+
+/// The profile used when none is given or selected
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Named auth profiles, persisted as `profiles.json` in the config directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    /// The profile selected with `rig profile use`
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub endpoint: String,
+}
+
+impl Profiles {
+    /// Loads the profiles at `path`; a missing file yields no profiles
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_private(path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// The profile to use: `requested` if given, else the active one, else
+    /// [`DEFAULT_PROFILE`]
+    pub fn resolve<'a>(&'a self, requested: Option<&'a str>) -> &'a str {
+        requested
+            .or(self.active.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Records `name` with its endpoint; the first profile becomes active
+    pub fn upsert(&mut self, name: &str, endpoint: &str) {
+        self.profiles.insert(
+            name.to_string(),
+            Profile {
+                endpoint: endpoint.to_string(),
+            },
+        );
+        if self.active.is_none() {
+            self.active = Some(name.to_string());
+        }
+    }
+
+    pub fn set_active(&mut self, name: &str) -> Result<()> {
+        if !self.profiles.contains_key(name) {
+            bail!("No profile named '{}'", name);
+        }
+        self.active = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Profile> {
+        let Some(profile) = self.profiles.remove(name) else {
+            bail!("No profile named '{}'", name);
+        };
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rig").join("profiles.json");
+        assert!(Profiles::load(&path).unwrap().profiles.is_empty());
+
+        let mut profiles = Profiles::default();
+        profiles.upsert("production", "https://api.max.dev");
+        profiles.upsert("staging", "https://staging.max.dev");
+        assert_eq!(profiles.resolve(None), "production");
+        assert_eq!(profiles.resolve(Some("staging")), "staging");
+
+        profiles.set_active("staging").unwrap();
+        assert!(profiles.set_active("missing").is_err());
+        profiles.save(&path).unwrap();
+
+        let mut loaded = Profiles::load(&path).unwrap();
+        assert_eq!(loaded.resolve(None), "staging");
+        assert_eq!(
+            loaded.get("production").unwrap().endpoint,
+            "https://api.max.dev"
+        );
+
+        loaded.remove("staging").unwrap();
+        assert_eq!(loaded.resolve(None), DEFAULT_PROFILE);
+        assert!(loaded.remove("staging").is_err());
+    }
+}
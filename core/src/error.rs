@@ -1,3 +1,5 @@
+use std::fmt;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, RigError>;
@@ -8,7 +10,10 @@ pub enum RigError {
     Http(#[from] reqwest::Error),
 
     #[error("WebSocket error: {0}")]
-    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+
+    #[error("Channel error: {0}")]
+    Channel(String),
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
@@ -22,22 +27,132 @@ pub enum RigError {
     #[error("Credential storage error: {0}")]
     Keyring(#[from] keyring::Error),
 
+    #[error("Credential store error: {0}")]
+    CredentialStore(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("Invalid URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
 
+    #[error("Not found: {message}{}", for_request(.request_id))]
+    NotFound {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Conflict: {message}{}", for_request(.request_id))]
+    Conflict {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Validation failed: {message}{}{}", list_fields(.fields), for_request(.request_id))]
+    Validation {
+        message: String,
+        fields: Vec<FieldError>,
+        request_id: Option<String>,
+    },
+
+    #[error("Permission denied: {message}{}", for_request(.request_id))]
+    Forbidden {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Rate limited: {message}{}{}", retry_in(.retry_after), for_request(.request_id))]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+        request_id: Option<String>,
+    },
+
     #[error("Generic error: {0}")]
     Generic(String),
 }
 
+/// A problem with one field of a rejected request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn for_request(request_id: &Option<String>) -> String {
+    match request_id {
+        Some(id) => format!(" (request id {id})"),
+        None => String::new(),
+    }
+}
+
+fn list_fields(fields: &[FieldError]) -> String {
+    if fields.is_empty() {
+        return String::new();
+    }
+    let fields: Vec<String> = fields.iter().map(ToString::to_string).collect();
+    format!(" ({})", fields.join("; "))
+}
+
+fn retry_in(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(after) => format!("; retry in {}s", after.as_secs().max(1)),
+        None => String::new(),
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for RigError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        RigError::WebSocket(Box::new(err))
+    }
+}
+
 impl RigError {
     pub fn auth<S: Into<String>>(msg: S) -> Self {
         RigError::Auth(msg.into())
     }
 
+    pub fn channel<S: Into<String>>(msg: S) -> Self {
+        RigError::Channel(msg.into())
+    }
+
+    pub fn credential_store<S: Into<String>>(msg: S) -> Self {
+        RigError::CredentialStore(msg.into())
+    }
+
     pub fn generic<S: Into<String>>(msg: S) -> Self {
         RigError::Generic(msg.into())
     }
+
+    /// The id Max assigned to the failed request, for support tickets
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            RigError::NotFound { request_id, .. }
+            | RigError::Conflict { request_id, .. }
+            | RigError::Validation { request_id, .. }
+            | RigError::Forbidden { request_id, .. }
+            | RigError::RateLimited { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// A stable name for the kind of error, for scripts to branch on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RigError::Http(_) | RigError::WebSocket(_) => "network",
+            RigError::Auth(_) => "auth",
+            RigError::NotFound { .. } => "not_found",
+            RigError::Conflict { .. } => "conflict",
+            RigError::Validation { .. } => "validation",
+            RigError::Forbidden { .. } => "forbidden",
+            RigError::RateLimited { .. } => "rate_limited",
+            _ => "error",
+        }
+    }
 }
@@ -0,0 +1,41 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tracing::warn;
+
+use crate::Result;
+
+/// Writes `data` to `path` readable only by the owner, replacing it atomically
+///
+/// Each write goes through its own temp file, so concurrent rig processes
+/// never write into each other's.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    let written = create_private(&tmp, data).and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        warn!("Could not replace {}: {}", path.display(), e);
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Creates `path` readable only by the owner; fails with `AlreadyExists`
+/// if it is there already
+pub(crate) fn create_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
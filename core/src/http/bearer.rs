@@ -0,0 +1,193 @@
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::{Request, Response, StatusCode};
+use std::sync::Arc;
+use tracing::debug;
+
+use super::{session_rejected, HttpClient, RequestOptions};
+use crate::auth::TokenProvider;
+use crate::{Result, RigError};
+
+impl HttpClient {
+    /// Authenticates requests with tokens from `tokens`
+    ///
+    /// Requests that already carry an `Authorization` header are sent as is.
+    /// When the server answers 401, the provider is asked once for a fresh
+    /// token and the request is resent; if that fails too, the request
+    /// fails with a hint to run `rig login`.
+    pub fn with_token_provider(mut self, tokens: Arc<dyn TokenProvider>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// The provider's current token, if there is one
+    pub(crate) async fn provided_token(&self) -> Option<String> {
+        let tokens = self.tokens.as_ref()?;
+        tokens.token().await.ok().flatten()
+    }
+
+    pub(super) async fn send_authorized(
+        &self,
+        mut request: Request,
+        options: &RequestOptions,
+    ) -> Result<Response> {
+        let Some(tokens) = self.tokens.clone() else {
+            return self.send_cached(request, options).await;
+        };
+        if request.headers().contains_key(AUTHORIZATION) {
+            return self.send_cached(request, options).await;
+        }
+
+        let token = tokens.token().await?;
+        if let Some(token) = &token {
+            set_bearer(&mut request, token)?;
+        }
+        // Streaming bodies cannot be resent with a refreshed token
+        let retry = request.try_clone();
+
+        let response = self.send_cached(request, options).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let Some(rejected) = token else {
+            return Err(RigError::auth("Not logged in; run `rig login` first"));
+        };
+        let Some(mut retry) = retry else {
+            return Err(session_rejected());
+        };
+
+        debug!(
+            "{} {} was rejected with 401; refreshing the token",
+            retry.method(),
+            retry.url()
+        );
+        let Some(token) = tokens.refresh(&rejected).await? else {
+            return Err(session_rejected());
+        };
+        set_bearer(&mut retry, &token)?;
+
+        let response = self.send_cached(retry, options).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(session_rejected());
+        }
+        Ok(response)
+    }
+}
+
+fn set_bearer(request: &mut Request, token: &str) -> Result<()> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|_| RigError::auth("The token contains characters not allowed in a header"))?;
+    value.set_sensitive(true);
+    request.headers_mut().insert(AUTHORIZATION, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Hands out `current` and, once rejected, whatever `fresh` holds
+    struct Tokens {
+        current: Option<String>,
+        fresh: Option<String>,
+        refreshed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TokenProvider for Tokens {
+        async fn token(&self) -> Result<Option<String>> {
+            Ok(self.current.clone())
+        }
+
+        async fn refresh(&self, rejected: &str) -> Result<Option<String>> {
+            self.refreshed.lock().unwrap().push(rejected.to_string());
+            Ok(self.fresh.clone())
+        }
+    }
+
+    fn tokens(current: Option<&str>, fresh: Option<&str>) -> Arc<Tokens> {
+        Arc::new(Tokens {
+            current: current.map(str::to_string),
+            fresh: fresh.map(str::to_string),
+            refreshed: Mutex::new(Vec::new()),
+        })
+    }
+
+    async fn server() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/private"))
+            .and(header("authorization", "Bearer fresh"))
+            .respond_with(ResponseTemplate::new(200))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/private"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn client(server: &MockServer, tokens: Arc<Tokens>) -> HttpClient {
+        HttpClient::new(&server.uri(), Duration::from_secs(5), 0)
+            .unwrap()
+            .with_token_provider(tokens)
+    }
+
+    #[tokio::test]
+    async fn test_attaches_bearer_token() {
+        let server = server().await;
+        let tokens = tokens(Some("fresh"), None);
+
+        let response = client(&server, tokens.clone())
+            .get("/private")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(tokens.refreshed.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_once_after_401() {
+        let server = server().await;
+        let tokens = tokens(Some("stale"), Some("fresh"));
+
+        let response = client(&server, tokens.clone())
+            .get("/private")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(*tokens.refreshed.lock().unwrap(), ["stale"]);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_session_asks_to_log_in() {
+        let server = server().await;
+
+        let err = client(&server, tokens(Some("stale"), None))
+            .get("/private")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RigError::Auth(_)));
+        assert!(err.to_string().contains("rig login"), "{err}");
+
+        let err = client(&server, tokens(Some("stale"), Some("revoked")))
+            .get("/private")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rig login"), "{err}");
+
+        let err = client(&server, tokens(None, None))
+            .get("/private")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Not logged in"), "{err}");
+    }
+}
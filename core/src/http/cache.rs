@@ -0,0 +1,448 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{
+    HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONNECTION, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED, RANGE, SET_COOKIE, TRANSFER_ENCODING,
+};
+use reqwest::{Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use url::Url;
+
+use super::{HttpClient, RequestOptions};
+use crate::files::write_private;
+use crate::{trace, Result, RigError};
+
+/// Size the cache is trimmed to unless configured otherwise
+const DEFAULT_CACHE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// An on-disk cache of GET responses
+///
+/// Cached responses younger than the TTL are served without asking the
+/// server; older ones are revalidated with `If-None-Match` and
+/// `If-Modified-Since`, and a 304 is answered from disk. Entries are keyed
+/// by URL and credentials, so profiles never see each other's responses.
+/// Once the cache outgrows its size limit, the least recently stored
+/// entries are removed.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_size: u64,
+}
+
+/// A cached response, stored as one JSON file
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    /// Seconds since the epoch when the response was stored or revalidated
+    stored_at: u64,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Base64 encoded
+    body: String,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Duration::ZERO,
+            max_size: DEFAULT_CACHE_SIZE,
+        }
+    }
+
+    /// How long a response is served without revalidating it; zero, the
+    /// default, revalidates every time
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Bytes the cache may take on disk
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes every cached response, returning how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let entries = match self.entries() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        for (path, _, _) in &entries {
+            fs::remove_file(path)?;
+        }
+        Ok(entries.len())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    fn load(&self, key: &str) -> Option<Entry> {
+        let data = fs::read(self.path(key)).ok()?;
+        // A corrupt or outdated entry is just a miss
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Writes `entry` and trims the cache; failures only cost a cache miss
+    fn store(&self, key: &str, entry: &Entry) {
+        let stored = serde_json::to_vec(entry)
+            .map_err(RigError::from)
+            .and_then(|data| write_private(&self.path(key), &data));
+        match stored {
+            Ok(()) => self.prune(),
+            Err(e) => warn!("Could not cache {}: {}", entry.url, e),
+        }
+    }
+
+    /// Removes the oldest entries until the cache fits its size limit
+    fn prune(&self) {
+        let Ok(mut entries) = self.entries() else {
+            return;
+        };
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, len, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+
+    /// Every entry file with its size and modification time
+    fn entries(&self) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let file = file?;
+            let path = file.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let metadata = file.metadata()?;
+                entries.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Entry {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.stored_at) < ttl.as_secs()
+    }
+
+    fn response(&self) -> Result<Response> {
+        let mut response = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        let body = STANDARD
+            .decode(&self.body)
+            .map_err(|e| RigError::generic(format!("Corrupt cache entry: {e}")))?;
+        let response = response
+            .body(body)
+            .map_err(|e| RigError::generic(format!("Corrupt cache entry: {e}")))?;
+        Ok(Response::from(response))
+    }
+}
+
+impl HttpClient {
+    /// Caches GET responses in `cache`
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sends `request` through the cache, if the client has one and the
+    /// request is a plain GET
+    pub(super) async fn send_cached(
+        &self,
+        mut request: Request,
+        options: &RequestOptions,
+    ) -> Result<Response> {
+        let Some(cache) = self
+            .cache
+            .as_ref()
+            .filter(|_| is_cacheable(&request) && !self.is_auth_url(request.url()))
+        else {
+            return self.send_with_retries(request, options).await;
+        };
+
+        let key = cache_key(&request);
+        let cached = cache.load(&key);
+        if let Some(entry) = &cached {
+            if entry.is_fresh(cache.ttl) {
+                debug!(
+                    "Serving {} from the cache",
+                    trace::redact_url(request.url())
+                );
+                return entry.response();
+            }
+            let validators = [
+                (IF_NONE_MATCH, entry.header(ETAG.as_str())),
+                (IF_MODIFIED_SINCE, entry.header(LAST_MODIFIED.as_str())),
+            ];
+            for (name, value) in validators {
+                if let Some(value) = value.and_then(|value| HeaderValue::from_str(value).ok()) {
+                    request.headers_mut().insert(name, value);
+                }
+            }
+        }
+
+        let url = request.url().clone();
+        let response = self.send_with_retries(request, options).await?;
+        match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(mut entry)) => {
+                debug!(
+                    "{} is unchanged; serving it from the cache",
+                    trace::redact_url(&url)
+                );
+                entry.stored_at = now();
+                cache.store(&key, &entry);
+                entry.response()
+            }
+            (StatusCode::OK, _) if is_storable(&response, cache) => {
+                let entry = Entry {
+                    url: trace::redact_url(&url),
+                    stored_at: now(),
+                    status: response.status().as_u16(),
+                    headers: stored_headers(&response),
+                    body: STANDARD.encode(response.bytes().await?),
+                };
+                cache.store(&key, &entry);
+                entry.response()
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Whether `url` is under the auth endpoints, whose answers must be
+    /// current; the base URL's path prefix is taken into account
+    fn is_auth_url(&self, url: &Url) -> bool {
+        self.url(&["auth", ""])
+            .is_ok_and(|auth| url.as_str().starts_with(auth.as_str()))
+    }
+}
+
+/// Only plain GETs are cached; requests with their own validators or a
+/// range are left alone
+fn is_cacheable(request: &Request) -> bool {
+    let headers = request.headers();
+    request.method() == Method::GET
+        && !headers.contains_key(RANGE)
+        && !headers.contains_key(IF_NONE_MATCH)
+        && !headers.contains_key(IF_MODIFIED_SINCE)
+}
+
+/// Whether a 200 can be stored: it must allow it, be revalidatable or cached
+/// for a while, and fit the cache
+fn is_storable(response: &Response, cache: &HttpCache) -> bool {
+    let headers = response.headers();
+    let no_store = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| {
+            value
+                .split(',')
+                .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+        });
+    let revalidatable = headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED);
+    let fits = response
+        .content_length()
+        .map_or(true, |len| len <= cache.max_size);
+    !no_store && (revalidatable || !cache.ttl.is_zero()) && fits
+}
+
+/// Headers worth replaying; cookies and connection details are dropped
+fn stored_headers(response: &Response) -> Vec<(String, String)> {
+    response
+        .headers()
+        .iter()
+        .filter(|(name, _)| {
+            ![SET_COOKIE, CONNECTION, TRANSFER_ENCODING, CONTENT_LENGTH].contains(name)
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// A digest of the URL and credentials, so tokens never land on disk
+fn cache_key(request: &Request) -> String {
+    let mut digest = Sha256::new();
+    digest.update(request.url().as_str());
+    if let Some(authorization) = request.headers().get(AUTHORIZATION) {
+        digest.update(b"\n");
+        digest.update(authorization.as_bytes());
+    }
+    digest
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer, cache: HttpCache) -> HttpClient {
+        HttpClient::new(&server.uri(), Duration::from_secs(5), 0)
+            .unwrap()
+            .with_cache(cache)
+    }
+
+    async fn fetch(client: &HttpClient) -> Value {
+        let response = client.get("/api/apps").await.unwrap();
+        assert_eq!(response.status(), 200);
+        response.json().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_revalidates_with_etags() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_json(json!({"apps": ["web"]})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let client = client(&server, HttpCache::new(dir.path()));
+        assert_eq!(fetch(&client).await, json!({"apps": ["web"]}));
+        // The second request is answered with 304 and served from disk
+        assert_eq!(fetch(&client).await, json!({"apps": ["web"]}));
+    }
+
+    #[tokio::test]
+    async fn test_serves_fresh_entries_without_asking() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"apps": []})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path()).ttl(Duration::from_secs(60));
+        let client = client(&server, cache.clone());
+        fetch(&client).await;
+        fetch(&client).await;
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.clear().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_skips_uncacheable_responses() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .insert_header("Cache-Control", "private, no-store")
+                    .set_body_json(json!({"apps": []})),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let client = client(&server, HttpCache::new(dir.path()));
+        fetch(&client).await;
+        fetch(&client).await;
+        assert_eq!(HttpCache::new(dir.path()).clear().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_skips_auth_endpoints() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/max/auth/me"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_json(json!({"email": "dev@max.dev"})),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path()).ttl(Duration::from_secs(60));
+        // Behind a gateway the auth endpoints are under its path
+        let client = HttpClient::new(&format!("{}/max/", server.uri()), Duration::from_secs(5), 0)
+            .unwrap()
+            .with_cache(cache.clone());
+        for _ in 0..2 {
+            let response = client.get("auth/me").await.unwrap();
+            assert_eq!(response.status(), 200);
+        }
+        assert_eq!(cache.clear().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_prunes_to_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path()).max_size(400);
+        for i in 0..4 {
+            let entry = Entry {
+                url: format!("https://api.max.dev/api/apps/{i}"),
+                stored_at: now(),
+                status: 200,
+                headers: Vec::new(),
+                body: STANDARD.encode([b'x'; 100]),
+            };
+            cache.store(&i.to_string(), &entry);
+            // Modification times must differ for the oldest to go first
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(cache.load("0").is_none());
+        assert!(cache.load("3").is_some());
+        let size: u64 = cache.entries().unwrap().iter().map(|(_, len, _)| len).sum();
+        assert!(size <= 400, "{size}");
+    }
+}
@@ -0,0 +1,264 @@
+use reqwest::{Response, StatusCode};
+use serde_json::Value;
+
+use super::{retry::retry_after, session_rejected};
+use crate::auth::describe_token;
+use crate::error::FieldError;
+use crate::trace::REQUEST_ID_HEADER;
+use crate::RigError;
+
+/// What Max says about a failed request
+///
+/// Errors come either flat, `{"message": …, "fields": …}`, or nested under
+/// `error`; field errors as a list of `{"field", "message"}` objects or a map
+/// from field to messages.
+#[derive(Debug, Default, PartialEq)]
+struct ErrorBody {
+    message: Option<String>,
+    fields: Vec<FieldError>,
+    request_id: Option<String>,
+}
+
+/// Turns a failed response into the error matching its status
+pub(super) async fn api_error(response: Response, token: Option<&str>, what: &str) -> RigError {
+    let status = response.status();
+    let header_request_id = response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let retry_after = retry_after(response.headers());
+
+    let body = response.bytes().await.unwrap_or_default();
+    let body = decode(&body);
+    let request_id = header_request_id.or(body.request_id);
+    let message = body.message;
+    let failed = || format!("{what} failed with status: {status}");
+
+    match status {
+        StatusCode::UNAUTHORIZED => session_rejected(),
+        StatusCode::FORBIDDEN => {
+            let message = message.unwrap_or_else(|| format!("{what} is not allowed"));
+            RigError::Forbidden {
+                // Name who the token belongs to and its scopes when it is a JWT
+                message: match token.and_then(describe_token) {
+                    Some(who) => format!("{message}; you are {who}"),
+                    None => message,
+                },
+                request_id,
+            }
+        }
+        StatusCode::NOT_FOUND => RigError::NotFound {
+            message: message.unwrap_or_else(failed),
+            request_id,
+        },
+        StatusCode::CONFLICT => RigError::Conflict {
+            message: message.unwrap_or_else(failed),
+            request_id,
+        },
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => RigError::Validation {
+            message: message.unwrap_or_else(failed),
+            fields: body.fields,
+            request_id,
+        },
+        StatusCode::TOO_MANY_REQUESTS => RigError::RateLimited {
+            message: message.unwrap_or_else(failed),
+            retry_after,
+            request_id,
+        },
+        _ => {
+            let message = message.map_or_else(failed, |message| format!("{}: {message}", failed()));
+            RigError::generic(match request_id {
+                Some(id) => format!("{message} (request id {id})"),
+                None => message,
+            })
+        }
+    }
+}
+
+fn decode(body: &[u8]) -> ErrorBody {
+    let Ok(value) = serde_json::from_slice::<Value>(body) else {
+        // Proxies answer with plain text or HTML; only short text is useful
+        let text = String::from_utf8_lossy(body).trim().to_string();
+        return ErrorBody {
+            message: (!text.is_empty() && text.len() <= 200 && !text.starts_with('<'))
+                .then_some(text),
+            ..ErrorBody::default()
+        };
+    };
+
+    let detail = match value.get("error") {
+        Some(error @ Value::Object(_)) => error,
+        _ => &value,
+    };
+    let string = |key: &str| {
+        detail
+            .get(key)
+            .or_else(|| value.get(key))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+
+    ErrorBody {
+        message: string("message").or_else(|| string("error")),
+        fields: detail
+            .get("fields")
+            .or_else(|| detail.get("errors"))
+            .map(decode_fields)
+            .unwrap_or_default(),
+        request_id: string("request_id"),
+    }
+}
+
+fn decode_fields(fields: &Value) -> Vec<FieldError> {
+    match fields {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| {
+                Some(FieldError {
+                    field: item.get("field")?.as_str()?.to_string(),
+                    message: item.get("message")?.as_str()?.to_string(),
+                })
+            })
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .flat_map(|(field, messages)| {
+                let messages = match messages {
+                    Value::Array(messages) => messages.iter().filter_map(Value::as_str).collect(),
+                    message => message.as_str().into_iter().collect::<Vec<_>>(),
+                };
+                messages.into_iter().map(|message| FieldError {
+                    field: field.clone(),
+                    message: message.to_string(),
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn field(field: &str, message: &str) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decode_error_bodies() {
+        let nested = json!({
+            "error": {
+                "code": "invalid",
+                "message": "The app is invalid",
+                "fields": [{"field": "name", "message": "is taken"}],
+            },
+            "request_id": "req_1",
+        });
+        assert_eq!(
+            decode(nested.to_string().as_bytes()),
+            ErrorBody {
+                message: Some("The app is invalid".to_string()),
+                fields: vec![field("name", "is taken")],
+                request_id: Some("req_1".to_string()),
+            }
+        );
+
+        let flat = json!({
+            "error": "invalid",
+            "errors": {"name": ["is too long", "is taken"], "region": "is unknown"},
+        });
+        let body = decode(flat.to_string().as_bytes());
+        assert_eq!(body.message.as_deref(), Some("invalid"));
+        assert_eq!(
+            body.fields,
+            [
+                field("name", "is too long"),
+                field("name", "is taken"),
+                field("region", "is unknown"),
+            ]
+        );
+
+        assert_eq!(
+            decode(b"upstream timed out").message.as_deref(),
+            Some("upstream timed out")
+        );
+        assert_eq!(decode(b"<html>Bad gateway</html>"), ErrorBody::default());
+    }
+
+    async fn error_for(template: ResponseTemplate) -> RigError {
+        let server = MockServer::start().await;
+        Mock::given(path("/fail"))
+            .respond_with(template)
+            .mount(&server)
+            .await;
+        let response = reqwest::get(format!("{}/fail", server.uri()))
+            .await
+            .unwrap();
+        api_error(response, None, "Loading the app").await
+    }
+
+    #[tokio::test]
+    async fn test_maps_statuses_to_errors() {
+        let err = error_for(
+            ResponseTemplate::new(404)
+                .insert_header("X-Request-Id", "req_404")
+                .set_body_json(json!({"message": "No app 'web'", "request_id": "ignored"})),
+        )
+        .await;
+        assert!(matches!(err, RigError::NotFound { .. }), "{err:?}");
+        assert_eq!(err.request_id(), Some("req_404"));
+        assert_eq!(
+            err.to_string(),
+            "Not found: No app 'web' (request id req_404)"
+        );
+
+        let err = error_for(ResponseTemplate::new(409).set_body_json(json!({
+            "error": {"message": "The name 'web' is taken"},
+            "request_id": "req_409",
+        })))
+        .await;
+        assert!(matches!(err, RigError::Conflict { .. }), "{err:?}");
+        assert_eq!(err.request_id(), Some("req_409"));
+
+        let err = error_for(ResponseTemplate::new(422).set_body_json(json!({
+            "message": "Invalid app",
+            "fields": [{"field": "name", "message": "is taken"}],
+        })))
+        .await;
+        assert_eq!(
+            err.to_string(),
+            "Validation failed: Invalid app (name: is taken)"
+        );
+
+        let err = error_for(ResponseTemplate::new(403)).await;
+        assert!(matches!(err, RigError::Forbidden { .. }), "{err:?}");
+        assert_eq!(err.kind(), "forbidden");
+
+        let err = error_for(ResponseTemplate::new(429).insert_header("Retry-After", "30")).await;
+        assert!(
+            matches!(
+                err,
+                RigError::RateLimited {
+                    retry_after: Some(after),
+                    ..
+                } if after == Duration::from_secs(30)
+            ),
+            "{err:?}"
+        );
+
+        let err = error_for(ResponseTemplate::new(500)).await;
+        assert_eq!(
+            err.to_string(),
+            "Generic error: Loading the app failed with status: 500 Internal Server Error"
+        );
+    }
+}
@@ -1,18 +1,47 @@
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 use url::Url;
 
+use crate::auth::TokenProvider;
+use crate::config::ConnectionConfig;
+use crate::network::NetworkSettings;
 use crate::{Result, RigError};
 
-#[derive(Debug, Clone)]
+mod bearer;
+mod cache;
+mod errors;
+mod pages;
+mod retry;
+mod tokens;
+
+pub use cache::HttpCache;
+pub use pages::ListOptions;
+pub use retry::{RequestOptions, IDEMPOTENCY_KEY};
+pub use tokens::{ApiToken, CreateApiToken, CreatedApiToken};
+
+#[derive(Clone)]
 pub struct HttpClient {
     client: Client,
     base_url: Url,
-    #[allow(dead_code)]
     timeout: Duration,
-    #[allow(dead_code)]
     retry_attempts: u32,
+    tokens: Option<Arc<dyn TokenProvider>>,
+    cache: Option<HttpCache>,
+}
+
+impl fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpClient")
+            .field("base_url", &self.base_url.as_str())
+            .field("timeout", &self.timeout)
+            .field("retry_attempts", &self.retry_attempts)
+            .field("authenticated", &self.tokens.is_some())
+            .field("cache", &self.cache)
+            .finish()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,11 +54,99 @@ pub struct AuthRequest {
 pub struct AuthResponse {
     pub token: String,
     pub expires_at: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// The signed-in user, as reported by the identity endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    pub username: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub org: Option<String>,
+}
+
+/// A device authorization response (RFC 8628)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// Seconds until the codes expire
+    pub expires_in: u64,
+    /// Minimum seconds between token polls
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+/// The outcome of polling the token endpoint during the device flow
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// The user has not approved the request yet
+    Pending,
+    /// Polling too fast; the interval must grow
+    SlowDown,
+    Approved(DeviceToken),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
 }
 
 impl HttpClient {
     pub fn new(base_url: &str, timeout: Duration, retry_attempts: u32) -> Result<Self> {
-        let client = Client::builder()
+        Self::build(base_url, timeout, retry_attempts, Client::builder())
+    }
+
+    /// A client for `base_url` using the timeout, retries, proxy and TLS
+    /// settings in `config`
+    pub fn from_config(base_url: &str, config: &ConnectionConfig) -> Result<Self> {
+        Self::with_network(
+            base_url,
+            Duration::from_secs(config.timeout),
+            config.retry_attempts,
+            &NetworkSettings::from_config(config)?,
+        )
+    }
+
+    pub(crate) fn with_network(
+        base_url: &str,
+        timeout: Duration,
+        retry_attempts: u32,
+        network: &NetworkSettings,
+    ) -> Result<Self> {
+        let builder = network.apply(Client::builder())?;
+        Self::build(base_url, timeout, retry_attempts, builder)
+    }
+
+    fn build(
+        base_url: &str,
+        timeout: Duration,
+        retry_attempts: u32,
+        builder: reqwest::ClientBuilder,
+    ) -> Result<Self> {
+        let client = builder
             .timeout(timeout)
             .user_agent(format!("rig-cli/{}", env!("CARGO_PKG_VERSION")))
             .build()?;
@@ -41,18 +158,24 @@ impl HttpClient {
             base_url,
             timeout,
             retry_attempts,
+            tokens: None,
+            cache: None,
         })
     }
 
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
     pub async fn authenticate(&self, username: &str, password: &str) -> Result<AuthResponse> {
         let auth_request = AuthRequest {
             username: username.to_string(),
             password: password.to_string(),
         };
 
-        let url = self.base_url.join("/auth/login")?;
+        let url = self.url(&["auth", "login"])?;
 
-        let response = self.client.post(url).json(&auth_request).send().await?;
+        let response = self.send(self.client.post(url).json(&auth_request)).await?;
 
         if !response.status().is_success() {
             return Err(RigError::auth(format!(
@@ -65,15 +188,193 @@ impl HttpClient {
         Ok(auth_response)
     }
 
+    /// Exchanges a refresh token for a new access token
+    pub async fn refresh(&self, refresh_token: &str) -> Result<AuthResponse> {
+        let refresh_request = RefreshRequest {
+            refresh_token: refresh_token.to_string(),
+        };
+
+        let url = self.url(&["auth", "refresh"])?;
+
+        let response = self
+            .send(self.client.post(url).json(&refresh_request))
+            .await?;
+
+        let status = response.status();
+        if status.is_client_error() {
+            return Err(RigError::auth(format!(
+                "The refresh token was rejected with status: {status}"
+            )));
+        }
+        if !status.is_success() {
+            return Err(RigError::generic(format!(
+                "Token refresh failed with status: {status}"
+            )));
+        }
+
+        let auth_response: AuthResponse = response.json().await?;
+        Ok(auth_response)
+    }
+
+    /// Fetches the identity `token` belongs to, or `None` if the server has
+    /// no identity endpoint
+    pub async fn identity(&self, token: &str) -> Result<Option<Identity>> {
+        let url = self.url(&["auth", "me"])?;
+
+        let response = self.send(self.client.get(url).bearer_auth(token)).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = ensure_success(response, Some(token), "Identity request").await?;
+
+        let identity: Identity = response.json().await?;
+        Ok(Some(identity))
+    }
+
+    /// Starts the device authorization flow for `client_id`
+    pub async fn request_device_code(&self, client_id: &str) -> Result<DeviceCode> {
+        let url = self.url(&["oauth", "device", "code"])?;
+
+        let response = self
+            .send(self.client.post(url).form(&[("client_id", client_id)]))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(RigError::auth(format!(
+                "Device authorization failed with status: {}",
+                response.status()
+            )));
+        }
+
+        let device_code: DeviceCode = response.json().await?;
+        Ok(device_code)
+    }
+
+    /// Polls the token endpoint once for a pending device authorization
+    pub async fn poll_device_token(
+        &self,
+        client_id: &str,
+        device_code: &str,
+    ) -> Result<DevicePoll> {
+        let url = self.url(&["oauth", "token"])?;
+
+        let response = self
+            .send(self.client.post(url).form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", device_code),
+                ("client_id", client_id),
+            ]))
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(DevicePoll::Approved(response.json().await?));
+        }
+        if !status.is_client_error() {
+            return Err(RigError::generic(format!(
+                "Device token request failed with status: {status}"
+            )));
+        }
+
+        let error: OAuthError = response.json().await?;
+        match error.error.as_str() {
+            "authorization_pending" => Ok(DevicePoll::Pending),
+            "slow_down" => Ok(DevicePoll::SlowDown),
+            "access_denied" => Err(RigError::auth("The login request was denied")),
+            "expired_token" => Err(RigError::auth(
+                "The login request expired; run `rig login --device` again",
+            )),
+            other => Err(RigError::auth(format!(
+                "Device login failed: {}",
+                error.error_description.as_deref().unwrap_or(other)
+            ))),
+        }
+    }
+
     pub async fn get(&self, path: &str) -> Result<Response> {
+        self.get_with(path, &RequestOptions::default()).await
+    }
+
+    pub async fn get_with(&self, path: &str, options: &RequestOptions) -> Result<Response> {
         let url = self.base_url.join(path)?;
-        let response = self.client.get(url).send().await?;
-        Ok(response)
+        self.execute(self.client.get(url), options).await
     }
 
     pub async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<Response> {
+        self.post_with(path, body, &RequestOptions::default()).await
+    }
+
+    pub async fn post_with<T: Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+        options: &RequestOptions,
+    ) -> Result<Response> {
         let url = self.base_url.join(path)?;
-        let response = self.client.post(url).json(body).send().await?;
-        Ok(response)
+        let options = self.keyed(&reqwest::Method::POST, options);
+        self.execute(self.client.post(url).json(body), &options)
+            .await
+    }
+
+    /// The URL of `segments` under the API base, each segment escaped
+    pub(crate) fn endpoint(&self, segments: &[&str]) -> Result<Url> {
+        let segments: Vec<&str> = ["api"].iter().chain(segments).copied().collect();
+        self.url(&segments)
+    }
+
+    /// The URL of `segments` under the base URL, each segment escaped
+    ///
+    /// A path on the base URL is kept as a prefix, e.g. for a gateway
+    /// serving Max at `https://gw.corp/max/`.
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = self.base_url.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+        url.path_segments_mut()
+            .map_err(|_| {
+                RigError::generic("The API endpoint must be a base URL, e.g. https://api.max.dev")
+            })?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// Starts a request to `url` with the client's settings
+    pub(crate) fn request(&self, method: reqwest::Method, url: Url) -> reqwest::RequestBuilder {
+        self.client.request(method, url)
     }
-}
\ No newline at end of file
+
+    /// Sends `request` with the client's default options
+    pub(crate) async fn send(&self, request: reqwest::RequestBuilder) -> Result<Response> {
+        self.execute(request, &RequestOptions::default()).await
+    }
+
+    /// [`ensure_success`] for requests authenticated by the token provider
+    pub(crate) async fn check(&self, response: Response, what: &str) -> Result<Response> {
+        let token = if response.status() == reqwest::StatusCode::FORBIDDEN {
+            self.provided_token().await
+        } else {
+            None
+        };
+        ensure_success(response, token.as_deref(), what).await
+    }
+}
+
+/// An error for a session the server no longer accepts
+pub(crate) fn session_rejected() -> RigError {
+    RigError::auth("The server rejected your session; run `rig login` to sign in again")
+}
+
+/// Passes successful responses through and decodes Max's error body for the
+/// rest
+pub(crate) async fn ensure_success(
+    response: Response,
+    token: Option<&str>,
+    what: &str,
+) -> Result<Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    Err(errors::api_error(response, token, what).await)
+}
@@ -0,0 +1,321 @@
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, Stream, StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::Url;
+
+use super::HttpClient;
+use crate::{Result, RigError};
+
+/// How much of a paginated list to fetch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    /// Items per request; the server default applies when omitted
+    pub page_size: Option<u32>,
+    /// Stop after this many items; all of them are fetched when omitted
+    pub limit: Option<usize>,
+}
+
+impl ListOptions {
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The page size to ask for; no more than the limit needs
+    fn request_size(&self) -> Option<u32> {
+        let limit = self
+            .limit
+            .map(|limit| u32::try_from(limit.max(1)).unwrap_or(u32::MAX));
+        match (self.page_size, limit) {
+            (Some(size), Some(limit)) => Some(size.min(limit)),
+            (size, limit) => size.or(limit),
+        }
+    }
+}
+
+/// One page of a list and where the next one is
+struct Page<T> {
+    items: Vec<T>,
+    /// A bad link fails only once this page's items are consumed
+    next: Option<Result<Url>>,
+}
+
+impl HttpClient {
+    /// Streams the items of a paginated list at `url`, fetching pages as the
+    /// stream is polled
+    ///
+    /// Max returns each page as `{"<key>": [...], "next_cursor": ...}`, or as
+    /// a bare array with the next page in a `Link: <...>; rel="next"` header.
+    /// The page size is sent as `page_size` and the cursor as `cursor`.
+    pub fn paginate<'a, T>(
+        &'a self,
+        url: Url,
+        key: &'a str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<T>> + Send + 'a
+    where
+        T: DeserializeOwned + Send + 'a,
+    {
+        let mut first = url;
+        if let Some(size) = options.request_size() {
+            first
+                .query_pairs_mut()
+                .append_pair("page_size", &size.to_string());
+        }
+
+        stream::try_unfold(Some(Ok(first)), move |next| async move {
+            let Some(url) = next else {
+                return Ok::<_, RigError>(None);
+            };
+            let page = self.fetch_page(url?, key).await?;
+            Ok(Some((
+                stream::iter(page.items.into_iter().map(Ok)),
+                page.next,
+            )))
+        })
+        .try_flatten()
+        .take(options.limit.unwrap_or(usize::MAX))
+    }
+
+    /// [`HttpClient::paginate`] for the list at `segments` under the API base
+    pub(crate) fn paginate_endpoint<'a, T>(
+        &'a self,
+        segments: &[&str],
+        key: &'a str,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<T>> + Send + 'a
+    where
+        T: DeserializeOwned + Send + 'a,
+    {
+        match self.endpoint(segments) {
+            Ok(url) => Either::Left(self.paginate(url, key, options)),
+            Err(e) => Either::Right(stream::once(future::ready(Err(e)))),
+        }
+    }
+
+    async fn fetch_page<T: DeserializeOwned>(&self, url: Url, key: &str) -> Result<Page<T>> {
+        let response = self.send(self.request(Method::GET, url.clone())).await?;
+        let response = self.check(response, &format!("Listing {key}")).await?;
+        let link = next_link(response.headers());
+
+        let mut body: Value = response.json().await?;
+        let cursor = body
+            .get("next_cursor")
+            .and_then(Value::as_str)
+            .filter(|cursor| !cursor.is_empty())
+            .map(str::to_string);
+        let items = match body.get_mut(key) {
+            Some(items) => items.take(),
+            None => body,
+        };
+        let items: Vec<T> = serde_json::from_value(items)?;
+
+        let next = match (cursor, link) {
+            // An empty page ends the list even if the server offers another
+            _ if items.is_empty() => None,
+            (Some(cursor), _) => Some(Ok(with_cursor(url, &cursor))),
+            (None, Some(link)) => Some(self.follow(&url, &link)),
+            (None, None) => None,
+        };
+        Ok(Page { items, next })
+    }
+
+    /// Resolves a `Link` target, refusing other origins so the token is not
+    /// sent elsewhere
+    fn follow(&self, current: &Url, link: &str) -> Result<Url> {
+        let next = current.join(link)?;
+        if next.origin() != self.base_url.origin() {
+            return Err(RigError::generic(format!(
+                "Refusing to follow a pagination link to another host: {next}"
+            )));
+        }
+        Ok(next)
+    }
+}
+
+fn with_cursor(mut url: Url, cursor: &str) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "cursor")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("cursor", cursor);
+    url
+}
+
+/// The `rel="next"` target of a `Link` header (RFC 8288)
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let (target, params) = link.trim().split_once(';')?;
+            let is_next = params.split(';').any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("rel=")
+                    .is_some_and(|rel| rel.trim_matches('"').split(' ').any(|rel| rel == "next"))
+            });
+            is_next.then(|| {
+                target
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+    use std::time::Duration;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer) -> HttpClient {
+        HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap()
+    }
+
+    fn names(range: std::ops::Range<u32>) -> Vec<Value> {
+        range
+            .map(|i| json!({ "name": format!("app-{i}") }))
+            .collect()
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Named {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_follows_cursors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .and(query_param_is_missing("cursor"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "apps": names(0..2),
+                "next_cursor": "c2",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .and(query_param("cursor", "c2"))
+            .and(query_param("page_size", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "apps": names(2..3),
+                "next_cursor": null,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let url = client.endpoint(&["apps"]).unwrap();
+        let options = ListOptions::default().page_size(2);
+        let apps: Vec<Named> = client
+            .paginate(url.clone(), "apps", &options)
+            .try_collect()
+            .await
+            .unwrap();
+        let listed: Vec<_> = apps.iter().map(|app| app.name.as_str()).collect();
+        assert_eq!(listed, ["app-0", "app-1", "app-2"]);
+
+        // The limit stops before the second page is fetched
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .and(query_param("page_size", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "apps": names(0..1),
+                "next_cursor": "c2",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let options = options.limit(1);
+        let apps: Vec<Named> = client
+            .paginate(url, "apps", &options)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(apps.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_follows_link_headers() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(names(0..2))
+                    .insert_header(
+                        "Link",
+                        r#"</api/apps/page/2>; rel="next", </api/apps>; rel="first""#,
+                    ),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/apps/page/2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(names(2..3))
+                    .insert_header("Link", "<https://elsewhere.example/apps>; rel=next"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let url = client.endpoint(&["apps"]).unwrap();
+        let results: Vec<Result<Named>> = client
+            .paginate(url, "apps", &ListOptions::default())
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 4);
+        assert!(results[..3].iter().all(Result::is_ok));
+        assert!(results[3].is_err());
+    }
+
+    #[test]
+    fn test_parse_link_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_link(&headers), None);
+
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(r#"<https://api.max.dev/api/apps?cursor=b>; rel="prev next""#),
+        );
+        assert_eq!(
+            next_link(&headers).as_deref(),
+            Some("https://api.max.dev/api/apps?cursor=b")
+        );
+    }
+
+    #[test]
+    fn test_request_size() {
+        assert_eq!(ListOptions::default().request_size(), None);
+        assert_eq!(ListOptions::default().limit(10).request_size(), Some(10));
+        let options = ListOptions::default().page_size(100);
+        assert_eq!(options.limit(20).request_size(), Some(20));
+        assert_eq!(options.limit(500).request_size(), Some(100));
+    }
+}
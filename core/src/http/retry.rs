@@ -0,0 +1,326 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Method, Request, RequestBuilder, Response, StatusCode};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::HttpClient;
+use crate::backoff::Backoff;
+use crate::{trace, Result};
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+/// Longest `Retry-After` we are willing to wait for
+const RETRY_AFTER_MAX: Duration = Duration::from_secs(120);
+
+/// Per-request overrides of the client's settings
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    pub retry_attempts: Option<u32>,
+    pub timeout: Option<Duration>,
+    /// Sent as the `Idempotency-Key` header, which also makes a POST safe
+    /// to retry
+    pub idempotency_key: Option<String>,
+}
+
+impl RequestOptions {
+    pub fn retry_attempts(mut self, retry_attempts: u32) -> Self {
+        self.retry_attempts = Some(retry_attempts);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+}
+
+impl HttpClient {
+    /// `options` for a `method` request, with a fresh uuid v4 as the
+    /// `Idempotency-Key` if the method is not idempotent and they carry none
+    ///
+    /// The key is set once, so every retry of the request repeats it.
+    pub(crate) fn keyed(&self, method: &Method, options: &RequestOptions) -> RequestOptions {
+        let mut options = options.clone();
+        if !is_idempotent(method) && options.idempotency_key.is_none() {
+            options.idempotency_key = Some(Uuid::new_v4().to_string());
+        }
+        options
+    }
+
+    /// Sends `request`, authenticated with the client's token provider when
+    /// it has one, retrying connect errors, 429s and 5xx responses with
+    /// exponential backoff
+    ///
+    /// Only idempotent methods and requests with an `Idempotency-Key` are
+    /// retried. A `Retry-After` header replaces the backoff delay. The last
+    /// response is returned as is once the attempts run out.
+    ///
+    /// See [`HttpClient::with_token_provider`] for how a 401 is handled, and
+    /// [`HttpCache`](super::HttpCache) for how GETs are cached.
+    pub async fn execute(
+        &self,
+        request: RequestBuilder,
+        options: &RequestOptions,
+    ) -> Result<Response> {
+        let mut request = request.timeout(options.timeout.unwrap_or(self.timeout));
+        if let Some(key) = &options.idempotency_key {
+            request = request.header(IDEMPOTENCY_KEY, key);
+        }
+        self.send_authorized(request.build()?, options).await
+    }
+
+    pub(super) async fn send_with_retries(
+        &self,
+        request: Request,
+        options: &RequestOptions,
+    ) -> Result<Response> {
+        let retryable =
+            is_idempotent(request.method()) || request.headers().contains_key(IDEMPOTENCY_KEY);
+        let retries = if retryable {
+            options.retry_attempts.unwrap_or(self.retry_attempts)
+        } else {
+            0
+        };
+        let mut backoff = Backoff::new(RETRY_BASE_DELAY, RETRY_MAX_DELAY);
+
+        loop {
+            let attempt = backoff.attempt() + 1;
+            trace::request(&request, attempt, retries + 1);
+            let started = Instant::now();
+
+            // Streaming bodies cannot be replayed
+            let Some(this_attempt) = request.try_clone() else {
+                return self
+                    .client
+                    .execute(request)
+                    .await
+                    .map_err(trace::without_url);
+            };
+            let result = self.client.execute(this_attempt).await;
+            match &result {
+                Ok(response) => trace::response(&request, response, started.elapsed()),
+                Err(e) => trace::failure(&request, e, started.elapsed()),
+            }
+
+            let (reason, retry_after) = match &result {
+                Ok(response) if is_retryable_status(response.status()) => (
+                    response.status().to_string(),
+                    retry_after(response.headers()),
+                ),
+                Err(e) if e.is_connect() => (trace::redact_error(&request, e), None),
+                _ => return result.map_err(trace::without_url),
+            };
+            if backoff.attempt() >= retries {
+                return result.map_err(trace::without_url);
+            }
+
+            let delay = backoff.next_delay();
+            let delay = retry_after.map_or(delay, |after| after.min(RETRY_AFTER_MAX));
+            warn!(
+                "{} {} failed ({}); retrying in {:?} (attempt {}/{})",
+                request.method(),
+                trace::redact_url(request.url()),
+                reason,
+                delay,
+                attempt + 1,
+                retries + 1
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Parses `Retry-After` given as seconds or an HTTP date
+pub(super) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::time::Instant;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer, retry_attempts: u32) -> HttpClient {
+        HttpClient::new(&server.uri(), Duration::from_secs(5), retry_attempts).unwrap()
+    }
+
+    async fn flaky(server: &MockServer, http_method: &str, failures: u64, status: u16) {
+        if failures > 0 {
+            Mock::given(method(http_method))
+                .and(path("/flaky"))
+                .respond_with(ResponseTemplate::new(status))
+                .up_to_n_times(failures)
+                .with_priority(1)
+                .mount(server)
+                .await;
+        }
+        Mock::given(method(http_method))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests() {
+        let server = MockServer::start().await;
+        flaky(&server, "GET", 2, 502).await;
+
+        let response = client(&server, 3).get("/flaky").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_retry_attempts() {
+        let server = MockServer::start().await;
+        flaky(&server, "GET", 5, 503).await;
+
+        let response = client(&server, 1).get("/flaky").await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_post_keeps_its_idempotency_key_across_retries() {
+        let server = MockServer::start().await;
+        flaky(&server, "POST", 2, 500).await;
+
+        let client = client(&server, 3);
+        let response = client.post("/flaky", &serde_json::json!({})).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 3);
+        let key = requests[0].headers.get(IDEMPOTENCY_KEY).unwrap();
+        assert!(Uuid::parse_str(key.to_str().unwrap()).is_ok());
+        assert!(requests
+            .iter()
+            .all(|request| request.headers.get(IDEMPOTENCY_KEY) == Some(key)));
+
+        // Each request gets its own key unless one is given
+        server.reset().await;
+        flaky(&server, "POST", 0, 500).await;
+        client.post("/flaky", &serde_json::json!({})).await.unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert_ne!(requests[0].headers.get(IDEMPOTENCY_KEY), Some(key));
+
+        server.reset().await;
+        flaky(&server, "POST", 1, 500).await;
+        let options = RequestOptions::default().idempotency_key("key-1");
+        let response = client
+            .post_with("/flaky", &serde_json::json!({}), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|request| request.headers.get(IDEMPOTENCY_KEY).unwrap() == "key-1"));
+    }
+
+    #[tokio::test]
+    async fn test_unkeyed_post_is_not_retried() {
+        let server = MockServer::start().await;
+        flaky(&server, "POST", 1, 500).await;
+
+        let client = client(&server, 3);
+        let url = client.base_url().join("/flaky").unwrap();
+        let response = client
+            .execute(
+                client.request(Method::POST, url),
+                &RequestOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_honors_retry_after_and_overrides() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = client(&server, 0);
+        let started = Instant::now();
+        let options = RequestOptions::default().retry_attempts(1);
+        let response = client.get_with("/flaky", &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_retries_connect_errors() {
+        // Nothing listens on the port once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let uri = format!("http://127.0.0.1:{port}");
+        let client = HttpClient::new(&uri, Duration::from_secs(1), 1).unwrap();
+        let started = Instant::now();
+
+        assert!(client.get("/").await.is_err());
+        assert!(started.elapsed() >= RETRY_BASE_DELAY / 2);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,175 @@
+use futures_util::Stream;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{HttpClient, ListOptions, RequestOptions};
+use crate::{Result, RigError};
+
+/// An API token for automation, as listed by the server; never includes
+/// the secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateApiToken {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Lifetime in seconds; the server default applies when omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+}
+
+/// A newly created API token; the server returns the secret only once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    pub secret: String,
+}
+
+impl HttpClient {
+    /// Creates a token; an `idempotency_key` in `options` makes re-running
+    /// the same create return the first token instead of a second one
+    pub async fn create_api_token(
+        &self,
+        request: &CreateApiToken,
+        options: &RequestOptions,
+    ) -> Result<CreatedApiToken> {
+        let url = self.endpoint(&["tokens"])?;
+        let options = self.keyed(&Method::POST, options);
+        let response = self
+            .execute(self.client.post(url).json(request), &options)
+            .await?;
+
+        let response = self.check(response, "Creating the API token").await?;
+        Ok(response.json().await?)
+    }
+
+    pub fn list_api_tokens(
+        &self,
+        options: &ListOptions,
+    ) -> impl Stream<Item = Result<ApiToken>> + Send + '_ {
+        self.paginate_endpoint(&["tokens"], "tokens", options)
+    }
+
+    pub async fn revoke_api_token(&self, id: &str) -> Result<()> {
+        let url = self.endpoint(&["tokens", id])?;
+        let response = self.send(self.client.delete(url)).await?;
+
+        match self.check(response, "Revoking the API token").await {
+            Err(RigError::NotFound { request_id, .. }) => Err(RigError::NotFound {
+                message: format!("No API token with id '{id}'"),
+                request_id,
+            }),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthClient, MemoryStore};
+    use futures_util::TryStreamExt;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer) -> HttpClient {
+        let http_client = HttpClient::new(&server.uri(), Duration::from_secs(5), 0).unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let auth = AuthClient::with_store(http_client.clone(), "default", store);
+        http_client.with_token_provider(Arc::new(auth.with_token("session")))
+    }
+
+    #[tokio::test]
+    async fn test_create_and_list_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/tokens"))
+            .and(header("authorization", "Bearer session"))
+            .and(header("idempotency-key", "ci-run-42"))
+            .and(body_json(json!({
+                "name": "deploy",
+                "scopes": ["apps:deploy"],
+                "ttl": 86400,
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "tok_1",
+                "name": "deploy",
+                "scopes": ["apps:deploy"],
+                "secret": "rig_secret",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/tokens"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "tokens": [{"id": "tok_1", "name": "deploy", "scopes": ["apps:deploy"]}],
+            })))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let created = client
+            .create_api_token(
+                &CreateApiToken {
+                    name: "deploy".to_string(),
+                    scopes: vec!["apps:deploy".to_string()],
+                    ttl: Some(86400),
+                },
+                &RequestOptions::default().idempotency_key("ci-run-42"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.token.id, "tok_1");
+        assert_eq!(created.secret, "rig_secret");
+
+        let tokens: Vec<ApiToken> = client
+            .list_api_tokens(&ListOptions::default())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].name, "deploy");
+    }
+
+    #[tokio::test]
+    async fn test_revoke_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/api/tokens/tok_1"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/api/tokens/tok_2"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        client.revoke_api_token("tok_1").await.unwrap();
+        assert!(matches!(
+            client.revoke_api_token("tok_2").await,
+            Err(RigError::Forbidden { .. })
+        ));
+        assert!(matches!(
+            client.revoke_api_token("missing").await,
+            Err(RigError::NotFound { .. })
+        ));
+    }
+}
@@ -1,11 +1,19 @@
+pub mod api;
 pub mod auth;
+mod backoff;
+pub mod channel;
 pub mod config;
 pub mod error;
+mod files;
 pub mod http;
+mod network;
+mod trace;
 
-pub use error::{Result, RigError};
+pub use error::{FieldError, Result, RigError};
 
 // Re-export commonly used types
-pub use auth::AuthClient;
+pub use api::MaxApi;
+pub use auth::{AuthClient, TokenProvider};
+pub use channel::{Channel, Socket};
 pub use config::Config;
 pub use http::HttpClient;
@@ -0,0 +1,283 @@
+//! The wire traces `--verbose` prints, with credentials redacted
+//!
+//! Events carry their details as fields, so they also come out structured
+//! under `--json`.
+
+use reqwest::header::HeaderMap;
+use reqwest::{Request, Response};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+use crate::channel::message::PHX_REPLY;
+use crate::channel::{Message, Payload, Reply};
+use crate::RigError;
+
+pub(crate) const REDACTED: &str = "[REDACTED]";
+
+/// Header, query and body field names whose values are never traced
+const SENSITIVE: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "password",
+    "secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "device_code",
+    "api_key",
+];
+
+/// Header carrying the id Max assigns to each request
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+fn is_sensitive(name: &str) -> bool {
+    SENSITIVE
+        .iter()
+        .any(|sensitive| sensitive.eq_ignore_ascii_case(name))
+}
+
+/// `url` with the values of sensitive query parameters replaced
+pub(crate) fn redact_url(url: &Url) -> String {
+    if !url.query_pairs().any(|(name, _)| is_sensitive(&name)) {
+        return url.to_string();
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if is_sensitive(&name) {
+                REDACTED.to_string()
+            } else {
+                value.into_owned()
+            };
+            (name.into_owned(), value)
+        })
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url.to_string()
+}
+
+pub(crate) fn redact_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_sensitive(name.as_str()) || value.is_sensitive() {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{name}: {value}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Replaces sensitive fields anywhere in `value`; with `secret_values`, e.g.
+/// for the secrets API, `value` fields are replaced too
+pub(crate) fn redact_json(value: &mut Value, secret_values: bool) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                if is_sensitive(name) || (secret_values && name == "value") {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value, secret_values);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_json(item, secret_values);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A request body as JSON or form fields, redacted
+fn redact_body(body: &[u8], secret_values: bool) -> String {
+    if let Ok(mut json) = serde_json::from_slice::<Value>(body) {
+        redact_json(&mut json, secret_values);
+        return json.to_string();
+    }
+    if let Ok(text) = std::str::from_utf8(body) {
+        if text.contains('=') && !text.contains(char::is_whitespace) {
+            let pairs: Vec<String> = url::form_urlencoded::parse(text.as_bytes())
+                .map(|(name, value)| {
+                    let value = if is_sensitive(&name) {
+                        REDACTED
+                    } else {
+                        &value
+                    };
+                    format!("{name}={value}")
+                })
+                .collect();
+            return pairs.join("&");
+        }
+    }
+    format!("<{} bytes>", body.len())
+}
+
+/// Traces a request about to be sent
+pub(crate) fn request(request: &Request, attempt: u32, attempts: u32) {
+    let secret_values = request
+        .url()
+        .path_segments()
+        .is_some_and(|mut segments| segments.any(|segment| segment == "secrets"));
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|body| redact_body(body, secret_values));
+
+    debug!(
+        method = %request.method(),
+        url = %redact_url(request.url()),
+        headers = %redact_headers(request.headers()),
+        body = body.as_deref().unwrap_or(""),
+        attempt,
+        attempts,
+        "HTTP request"
+    );
+}
+
+/// Traces the response to `request`
+pub(crate) fn response(request: &Request, response: &Response, latency: Duration) {
+    let request_id = response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    debug!(
+        method = %request.method(),
+        url = %redact_url(request.url()),
+        status = response.status().as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        request_id = request_id.unwrap_or(""),
+        "HTTP response"
+    );
+}
+
+/// `error` for `request`, with the URL it embeds redacted
+pub(crate) fn redact_error(request: &Request, error: &reqwest::Error) -> String {
+    error
+        .to_string()
+        .replace(request.url().as_str(), &redact_url(request.url()))
+}
+
+/// `error` without the URL it embeds, for errors leaving the HTTP layer;
+/// the URL may carry a token, e.g. a long-poll session
+pub(crate) fn without_url(error: reqwest::Error) -> RigError {
+    RigError::Http(error.without_url())
+}
+
+/// Traces a request that got no response
+pub(crate) fn failure(request: &Request, error: &reqwest::Error, latency: Duration) {
+    debug!(
+        method = %request.method(),
+        url = %redact_url(request.url()),
+        latency_ms = latency.as_millis() as u64,
+        error = %redact_error(request, error),
+        "HTTP request failed"
+    );
+}
+
+/// Traces a socket message
+pub(crate) fn frame(direction: &str, message: &Message) {
+    frame_event(
+        direction,
+        &message.topic,
+        &message.event,
+        message.msg_ref.as_deref(),
+        &message.payload,
+        None,
+    );
+}
+
+/// Traces a reply; `latency` is the round trip when it answers a push
+pub(crate) fn reply(topic: &str, msg_ref: Option<&str>, reply: &Reply, latency: Option<Duration>) {
+    frame_event(
+        "received",
+        topic,
+        PHX_REPLY,
+        msg_ref,
+        &reply.response,
+        latency,
+    );
+}
+
+fn frame_event(
+    direction: &str,
+    topic: &str,
+    event: &str,
+    msg_ref: Option<&str>,
+    payload: &Payload,
+    latency: Option<Duration>,
+) {
+    let payload = match payload {
+        Payload::Json(json) => {
+            let mut json = json.clone();
+            redact_json(&mut json, false);
+            json.to_string()
+        }
+        Payload::Binary(bytes) => format!("<{} bytes>", bytes.len()),
+    };
+
+    debug!(
+        direction,
+        topic,
+        event,
+        msg_ref = msg_ref.unwrap_or(""),
+        latency_ms = latency.map(|latency| latency.as_millis() as u64),
+        payload = %payload,
+        "Socket frame"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use serde_json::json;
+
+    #[test]
+    fn test_redacts_urls_and_headers() {
+        let url = Url::parse("wss://api.max.dev/socket/websocket?token=abc&vsn=2.0.0").unwrap();
+        let redacted = redact_url(&url);
+        assert!(!redacted.contains("abc"), "{redacted}");
+        assert!(redacted.contains("vsn=2.0.0"), "{redacted}");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer abc"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        assert_eq!(
+            redact_headers(&headers),
+            "authorization: [REDACTED], accept: */*"
+        );
+    }
+
+    #[test]
+    fn test_redacts_bodies() {
+        let login = json!({"username": "ada", "password": "hunter2"}).to_string();
+        assert_eq!(
+            redact_body(login.as_bytes(), false),
+            r#"{"password":"[REDACTED]","username":"ada"}"#
+        );
+
+        let secret = json!({"value": "s3cr3t"}).to_string();
+        assert!(!redact_body(secret.as_bytes(), true).contains("s3cr3t"));
+        assert!(redact_body(secret.as_bytes(), false).contains("s3cr3t"));
+
+        let form = "client_id=rig-cli&device_code=dev-123";
+        assert_eq!(
+            redact_body(form.as_bytes(), false),
+            "client_id=rig-cli&device_code=[REDACTED]"
+        );
+        assert_eq!(redact_body(&[0xff, 0xfe], false), "<2 bytes>");
+    }
+}
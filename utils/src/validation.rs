@@ -56,6 +56,39 @@ pub fn validate_env_var_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Parses a duration such as `90`, `45m`, `12h` or `30d` into seconds
+pub fn parse_duration(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => input.split_at(index),
+        None => (input, "s"),
+    };
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration '{}'; expected e.g. 30d or 12h", input))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid duration unit '{}'; expected s, m, h, d or w",
+                unit
+            ))
+        }
+    };
+
+    if value == 0 {
+        return Err(anyhow::anyhow!("Duration must be greater than zero"));
+    }
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too long", input))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -82,4 +115,17 @@ mod tests {
         assert!(validate_env_var_name("my_var").is_err());
         assert!(validate_env_var_name("MY-VAR").is_err());
     }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), 90);
+        assert_eq!(parse_duration("45m").unwrap(), 2700);
+        assert_eq!(parse_duration("12h").unwrap(), 43200);
+        assert_eq!(parse_duration("30d").unwrap(), 2592000);
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("0d").is_err());
+        assert!(parse_duration("10y").is_err());
+        assert!(parse_duration("d").is_err());
+    }
 }